@@ -0,0 +1,307 @@
+//! Recognizes the archive format from the content of the file rather than its
+//! name.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+
+use super::Format;
+use crate::error::ExtractError;
+
+const ZIP_LOCAL_HEADER: &[u8] = b"PK\x03\x04";
+const ZIP_END_OF_CENTRAL_DIRECTORY: &[u8] = b"PK\x05\x06";
+const ZIP_SPANNED: &[u8] = b"PK\x07\x08";
+const GZIP: &[u8] = b"\x1f\x8b";
+const BZIP2: &[u8] = b"BZh";
+const XZ: &[u8] = b"\xfd7zXZ\x00";
+const ZSTD: &[u8] = b"\x28\xb5\x2f\xfd";
+
+const TAR_BLOCK: usize = 512;
+/// The end of central directory record is 22 bytes followed by a comment of
+/// at most 65535 bytes.
+const ZIP_EOCD_SEARCH: u64 = 22 + 65535;
+
+/// Sniffs the format of `file` and rewinds it to the start.
+pub(crate) fn detect(file: &mut File) -> Result<Format, ExtractError> {
+    file.rewind()?;
+    let head = read_up_to(&mut *file, TAR_BLOCK)?;
+
+    let format = if head.starts_with(ZIP_LOCAL_HEADER)
+        || head.starts_with(ZIP_END_OF_CENTRAL_DIRECTORY)
+        || head.starts_with(ZIP_SPANNED)
+    {
+        Some(Format::Zip)
+    } else if head.starts_with(GZIP) {
+        Some(compressed_tar(file, Format::TarGz, "gzip")?)
+    } else if head.starts_with(BZIP2) {
+        Some(compressed_tar(file, Format::TarBz2, "bzip2")?)
+    } else if head.starts_with(XZ) {
+        Some(compressed_tar(file, Format::TarXz, "xz")?)
+    } else if head.starts_with(ZSTD) {
+        Some(compressed_tar(file, Format::TarZst, "zstd")?)
+    } else if is_tar_header(&head) {
+        Some(Format::Tar)
+    } else if has_zip_trailer(file)? {
+        // Self-extracting archives put an executable stub before the zip data.
+        Some(Format::Zip)
+    } else {
+        None
+    };
+
+    file.rewind()?;
+    format.ok_or_else(|| ExtractError::UnrecognizedFormat {
+        detected: describe_unknown(&head, true),
+    })
+}
+
+/// Sniffs the format of a stream from its magic bytes alone, returning them
+/// so they can be put back in front of the rest. There is no looking inside
+/// compressed data or for a trailing zip directory here.
+pub(crate) fn detect_stream(reader: &mut dyn Read) -> Result<(Format, Vec<u8>), ExtractError> {
+    let head = read_up_to(&mut *reader, TAR_BLOCK)?;
+    let format =
+        if head.starts_with(ZIP_LOCAL_HEADER) || head.starts_with(ZIP_END_OF_CENTRAL_DIRECTORY) {
+            Format::Zip
+        } else if head.starts_with(GZIP) {
+            Format::TarGz
+        } else if head.starts_with(BZIP2) {
+            Format::TarBz2
+        } else if head.starts_with(XZ) {
+            Format::TarXz
+        } else if head.starts_with(ZSTD) {
+            Format::TarZst
+        } else if is_tar_header(&head) {
+            Format::Tar
+        } else {
+            return Err(ExtractError::UnrecognizedFormat {
+                detected: describe_unknown(&head, false),
+            });
+        };
+    Ok((format, head))
+}
+
+/// Checks that a compressed stream actually holds a tar archive.
+fn compressed_tar(
+    file: &mut File,
+    format: Format,
+    compression: &str,
+) -> Result<Format, ExtractError> {
+    file.rewind()?;
+    let input = BufReader::new(&*file);
+    let decoder: Box<dyn Read + '_> = match format {
+        Format::TarGz => Box::new(flate2::read::GzDecoder::new(input)),
+        Format::TarBz2 => Box::new(bzip2::read::BzDecoder::new(input)),
+        Format::TarXz => Box::new(xz2::read::XzDecoder::new(input)),
+        _ => Box::new(zstd::stream::read::Decoder::with_buffer(input)?),
+    };
+    match read_up_to(decoder, TAR_BLOCK) {
+        Ok(block) if is_tar_header(&block) => Ok(format),
+        Ok(_) => Err(ExtractError::UnrecognizedFormat {
+            detected: format!(
+                "{} compressed data that does not contain a tar archive",
+                compression
+            ),
+        }),
+        Err(err) => Err(ExtractError::UnrecognizedFormat {
+            detected: format!(
+                "{} signature, but the stream cannot be decompressed: {}",
+                compression, err
+            ),
+        }),
+    }
+}
+
+/// A tar header either carries the ustar magic or, for pre-POSIX archives, a
+/// valid checksum. An all-zero block is the end marker of an empty archive.
+fn is_tar_header(block: &[u8]) -> bool {
+    if block.len() < TAR_BLOCK {
+        return false;
+    }
+    if &block[257..262] == b"ustar" || block[..TAR_BLOCK].iter().all(|&b| b == 0) {
+        return true;
+    }
+    let stored = std::str::from_utf8(&block[148..156])
+        .ok()
+        .map(|field| field.trim_matches(|c: char| c == '\0' || c == ' '))
+        .and_then(|field| u32::from_str_radix(field, 8).ok());
+    let computed: u32 = block[..TAR_BLOCK]
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| {
+            if (148..156).contains(&i) {
+                u32::from(b' ')
+            } else {
+                u32::from(b)
+            }
+        })
+        .sum();
+    stored == Some(computed)
+}
+
+fn has_zip_trailer(file: &mut File) -> io::Result<bool> {
+    let len = file.seek(SeekFrom::End(0))?;
+    let start = len.saturating_sub(ZIP_EOCD_SEARCH);
+    file.seek(SeekFrom::Start(start))?;
+    let tail = read_up_to(&mut *file, (len - start) as usize)?;
+    Ok(tail
+        .windows(ZIP_END_OF_CENTRAL_DIRECTORY.len())
+        .any(|window| window == ZIP_END_OF_CENTRAL_DIRECTORY))
+}
+
+fn describe_unknown(head: &[u8], searched_trailer: bool) -> String {
+    if head.is_empty() {
+        return "an empty file".to_string();
+    }
+    let bytes = head
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "leading bytes {} matching none of zip, tar, gzip, bzip2, xz or zstd{}",
+        bytes,
+        if searched_trailer {
+            ", and no zip end of central directory"
+        } else {
+            ""
+        }
+    )
+}
+
+fn read_up_to<R: Read>(reader: R, limit: usize) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(limit);
+    reader.take(limit as u64).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{Cursor, Write};
+
+    use super::*;
+    use crate::test_util::scratch_dir;
+
+    fn tar() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        builder
+            .append_data(&mut header, "hello.txt", &b"hello"[..])
+            .unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    fn zip() -> Vec<u8> {
+        let mut zip = ::zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("hello.txt", Default::default()).unwrap();
+        zip.write_all(b"hello").unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn detect_bytes(name: &str, bytes: &[u8]) -> Result<Format, ExtractError> {
+        let dir = scratch_dir(&format!("detect-{}", name));
+        let path = dir.join("archive");
+        fs::write(&path, bytes).unwrap();
+        let mut file = File::open(&path).unwrap();
+        let format = detect(&mut file);
+        if format.is_ok() {
+            assert_eq!(file.stream_position().unwrap(), 0);
+        }
+        fs::remove_dir_all(dir).unwrap();
+        format
+    }
+
+    fn unrecognized(result: Result<Format, ExtractError>) -> String {
+        match result {
+            Err(ExtractError::UnrecognizedFormat { detected }) => detected,
+            result => panic!("expected an unrecognized format, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn formats_are_told_by_their_magic_bytes() {
+        assert_eq!(detect_bytes("zip", &zip()).unwrap(), Format::Zip);
+        assert_eq!(detect_bytes("tar", &tar()).unwrap(), Format::Tar);
+        assert_eq!(
+            detect_bytes("tar-gz", &gzip(&tar())).unwrap(),
+            Format::TarGz
+        );
+        let zstd = zstd::encode_all(&tar()[..], 0).unwrap();
+        assert_eq!(detect_bytes("tar-zst", &zstd).unwrap(), Format::TarZst);
+    }
+
+    #[test]
+    fn self_extracting_zips_are_found_by_their_trailer() {
+        let mut sfx = b"MZ\x90\x00 an executable stub".to_vec();
+        sfx.extend_from_slice(&zip());
+        assert_eq!(detect_bytes("sfx", &sfx).unwrap(), Format::Zip);
+    }
+
+    #[test]
+    fn old_tar_headers_are_told_by_their_checksum() {
+        let mut tar = tar();
+        tar[257..265].fill(0);
+        let checksum: u32 = tar[..TAR_BLOCK]
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| {
+                if (148..156).contains(&i) {
+                    32
+                } else {
+                    u32::from(b)
+                }
+            })
+            .sum();
+        tar[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+        assert_eq!(detect_bytes("v7-tar", &tar).unwrap(), Format::Tar);
+        tar[0] ^= 1;
+        assert!(detect_bytes("bad-checksum", &tar).is_err());
+    }
+
+    #[test]
+    fn compressed_data_must_hold_a_tar() {
+        let detected = unrecognized(detect_bytes("gz-text", &gzip(b"just some text")));
+        assert_eq!(
+            detected,
+            "gzip compressed data that does not contain a tar archive"
+        );
+        let detected = unrecognized(detect_bytes("gz-corrupt", b"\x1f\x8bnot gzip"));
+        assert!(detected.starts_with("gzip signature, but the stream cannot be decompressed"));
+    }
+
+    #[test]
+    fn unknown_content_is_described() {
+        assert_eq!(unrecognized(detect_bytes("empty", b"")), "an empty file");
+        assert_eq!(
+            unrecognized(detect_bytes("text", b"plain text file")),
+            "leading bytes 70 6c 61 69 6e 20 74 65 matching none of zip, tar, gzip, bzip2, xz \
+             or zstd, and no zip end of central directory"
+        );
+    }
+
+    #[test]
+    fn streams_are_told_by_their_magic_bytes_alone() {
+        let mut gzip_text = Cursor::new(gzip(b"just some text"));
+        let (format, head) = detect_stream(&mut gzip_text).unwrap();
+        assert_eq!(format, Format::TarGz);
+        assert_eq!(head, gzip_text.into_inner());
+
+        let (format, _) = detect_stream(&mut Cursor::new(tar())).unwrap();
+        assert_eq!(format, Format::Tar);
+
+        let mut sfx = b"MZ".to_vec();
+        sfx.extend_from_slice(&zip());
+        let detected = match detect_stream(&mut Cursor::new(sfx)) {
+            Err(ExtractError::UnrecognizedFormat { detected }) => detected,
+            result => panic!("expected an unrecognized format, got {:?}", result),
+        };
+        assert!(!detected.contains("end of central directory"));
+    }
+}
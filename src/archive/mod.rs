@@ -0,0 +1,251 @@
+//! Archive backends the extractor reads entries from.
+
+mod detect;
+mod shared_file;
+mod tar;
+mod zip;
+mod zip_stream;
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+
+use time::OffsetDateTime;
+
+use crate::error::ExtractError;
+use crate::extractor::FileKind;
+
+pub(crate) use self::detect::detect;
+
+/// Container format of an archive, including the compression wrapped around
+/// tar streams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Zip,
+    Tar,
+    TarGz,
+    TarBz2,
+    TarXz,
+    TarZst,
+}
+
+impl Format {
+    pub fn is_tar(self) -> bool {
+        self != Format::Zip
+    }
+}
+
+/// Metadata of a single archive entry.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// Position of the entry in the archive.
+    pub index: usize,
+    /// The name as stored in the archive.
+    pub name: String,
+    /// The name as a relative path, or `None` if it would leave the output
+    /// directory.
+    pub enclosed_name: Option<PathBuf>,
+    pub kind: FileKind,
+    /// What a symlink points to, or for a hardlink the name of the entry it
+    /// links to.
+    pub link_target: Option<PathBuf>,
+    pub size: u64,
+    /// Size of the stored data; `None` for tar, which compresses the whole
+    /// stream rather than single entries.
+    pub compressed_size: Option<u64>,
+    pub method: String,
+    /// Last modification time. For zip this comes from the extended timestamp
+    /// extra field if present; the basic field is local time without a zone,
+    /// which is read in the local time zone.
+    pub modified: Option<OffsetDateTime>,
+    /// Unix file mode including the file type bits, if the archive records one.
+    pub unix_mode: Option<u32>,
+    /// `None` where the format stores no CRC-32, as for tar and AE-2
+    /// encrypted zip entries.
+    pub crc32: Option<u32>,
+    pub encryption: Option<Encryption>,
+}
+
+/// How an entry is encrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encryption {
+    /// Traditional PKWARE encryption.
+    ZipCrypto,
+    /// WinZip AES with a key of the given length.
+    Aes { bits: u16 },
+    /// A scheme that cannot be decrypted, such as PKWARE strong encryption.
+    Unsupported,
+}
+
+pub(crate) type VisitEntry<'a> =
+    dyn FnMut(usize, Result<&mut dyn Read, ExtractError>) -> Result<(), ExtractError> + 'a;
+
+pub(crate) type ReadVisit<'a> = dyn FnMut(&mut dyn Read) -> Result<(), ExtractError> + 'a;
+
+pub(crate) type StreamVisit<'a> =
+    dyn FnMut(&Entry, Result<&mut dyn Read, ExtractError>) -> Result<(), ExtractError> + 'a;
+
+pub(crate) trait ArchiveBackend {
+    /// Lists the entries the extractor knows how to write.
+    fn entries(&mut self) -> Result<Vec<Entry>, ExtractError>;
+
+    /// Streams the data of the entries at `indices`, given in ascending order,
+    /// to `visit`. Entries that cannot be opened are handed over as errors so
+    /// the caller decides whether to go on.
+    fn read_entries(
+        &mut self,
+        indices: &[usize],
+        visit: &mut VisitEntry<'_>,
+    ) -> Result<(), ExtractError>;
+
+    /// Streams the data of the entry stored under exactly `name` to `visit`.
+    /// Fails with [`ExtractError::EntryNotFound`] if there is none and with
+    /// [`ExtractError::IsDirectory`] if it is a directory, also when `name`
+    /// lacks the trailing slash directory names are stored with.
+    fn read_by_name(&mut self, name: &str, visit: &mut ReadVisit<'_>) -> Result<(), ExtractError> {
+        let entries = self.entries()?;
+        let dir_name = format!("{}/", name);
+        let entry = entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .or_else(|| entries.iter().find(|entry| entry.name == dir_name))
+            .ok_or_else(|| ExtractError::EntryNotFound(name.to_string()))?;
+        if entry.kind == FileKind::Directory {
+            return Err(ExtractError::IsDirectory(name.to_string()));
+        }
+        self.read_entries(&[entry.index], &mut |_, reader| visit(reader?))
+    }
+
+    /// Password used for encrypted entries. Formats without encryption ignore
+    /// it.
+    fn set_password(&mut self, _password: Option<Vec<u8>>) {}
+
+    /// Streams every entry together with its data in a single pass, which is
+    /// all a backend reading from a stream can do.
+    fn stream_entries(&mut self, visit: &mut StreamVisit<'_>) -> Result<(), ExtractError> {
+        let entries = self.entries()?;
+        let indices = entries.iter().map(|entry| entry.index).collect::<Vec<_>>();
+        let mut entries = entries.iter();
+        self.read_entries(&indices, &mut |_, reader| {
+            visit(entries.next().unwrap(), reader)
+        })
+    }
+
+    /// Counts the stored bytes of the entry being streamed as they are read,
+    /// for formats whose entries need not declare their compressed size ahead
+    /// of the data. `None` if every entry declares it.
+    fn compressed_read(&self) -> Option<Arc<AtomicU64>> {
+        None
+    }
+
+    /// Opens another reader on the same archive for use on another thread, or
+    /// returns `None` if the format can only be read front to back.
+    fn try_clone(&self) -> Option<Box<dyn ArchiveBackend + Send>> {
+        None
+    }
+}
+
+pub(crate) fn open(file: File, format: Format) -> Result<Box<dyn ArchiveBackend>, ExtractError> {
+    let backend: Box<dyn ArchiveBackend> = match format {
+        Format::Zip => Box::new(zip::ZipBackend::new(file)?),
+        _ => Box::new(tar::TarBackend::new(file, format)),
+    };
+    Ok(backend)
+}
+
+/// Opens an archive that can only be read front to back, sniffing the format
+/// from its first bytes unless it is given.
+pub(crate) fn open_stream(
+    mut reader: Box<dyn Read>,
+    format: Option<Format>,
+) -> Result<(Format, Box<dyn ArchiveBackend>), ExtractError> {
+    let format = match format {
+        Some(format) => format,
+        None => {
+            let (format, head) = detect::detect_stream(&mut reader)?;
+            reader = Box::new(io::Cursor::new(head).chain(reader));
+            format
+        }
+    };
+    let backend: Box<dyn ArchiveBackend> = match format {
+        Format::Zip => Box::new(zip_stream::ZipStreamBackend::new(reader)),
+        _ => Box::new(tar::TarStreamBackend::new(reader, format)?),
+    };
+    Ok((format, backend))
+}
+
+/// Reports a failure to decode the data of an archive as invalid data, so it
+/// counts as corruption whatever kind the decoder gave it: flate2 reports a
+/// corrupt deflate stream as invalid input, and tar, bzip2, xz and zstd use
+/// other kinds still. Errors of the operating system reading the archive are
+/// left as they are.
+pub(crate) fn decode_error(err: io::Error) -> io::Error {
+    match err.kind() {
+        io::ErrorKind::Interrupted | io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+            err
+        }
+        _ if err.raw_os_error().is_some() => err,
+        _ => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
+
+/// Reads through a decoder, passing its errors through [`decode_error`].
+pub(crate) struct Decoded<R>(pub R);
+
+impl<R: Read> Read for Decoded<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf).map_err(decode_error)
+    }
+}
+
+fn not_seekable() -> ExtractError {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "a stream can only be read once, from front to back",
+    )
+    .into()
+}
+
+/// Returns `name` as a relative path if it stays inside the directory it is
+/// joined onto.
+pub(crate) fn enclosed_name(name: &str) -> Option<PathBuf> {
+    if name.contains('\0') {
+        return None;
+    }
+    let path = Path::new(name);
+    let mut depth = 0usize;
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => return None,
+            Component::ParentDir => depth = depth.checked_sub(1)?,
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => (),
+        }
+    }
+    Some(path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decoder_errors_become_invalid_data() {
+        let err = decode_error(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "corrupt deflate stream",
+        ));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "corrupt deflate stream");
+    }
+
+    #[test]
+    fn os_errors_are_kept() {
+        let err = decode_error(io::Error::from_raw_os_error(5));
+        assert_eq!(err.raw_os_error(), Some(5));
+        let err = decode_error(io::ErrorKind::UnexpectedEof.into());
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,223 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek};
+use std::path::PathBuf;
+
+use tar::EntryType;
+use time::OffsetDateTime;
+
+use super::{
+    decode_error, enclosed_name, not_seekable, ArchiveBackend, Decoded, Entry, Format, StreamVisit,
+    VisitEntry,
+};
+use crate::error::ExtractError;
+use crate::extractor::FileKind;
+
+const S_IFREG: u32 = 0o100000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFLNK: u32 = 0o120000;
+
+/// Reads plain and compressed tar streams. Tar has no index, so every pass
+/// over the entries decompresses the archive from the start.
+pub(crate) struct TarBackend {
+    file: File,
+    format: Format,
+}
+
+impl TarBackend {
+    pub fn new(file: File, format: Format) -> Self {
+        Self { file, format }
+    }
+
+    fn archive(&mut self) -> io::Result<tar::Archive<Box<dyn Read + '_>>> {
+        self.file.rewind()?;
+        Ok(tar::Archive::new(decoder(
+            BufReader::new(&self.file),
+            self.format,
+        )?))
+    }
+}
+
+/// Reads a tar archive from a stream in a single pass.
+pub(crate) struct TarStreamBackend {
+    /// `None` once the stream has been walked.
+    archive: Option<tar::Archive<Box<dyn Read>>>,
+}
+
+impl TarStreamBackend {
+    pub fn new(reader: Box<dyn Read>, format: Format) -> io::Result<Self> {
+        Ok(Self {
+            archive: Some(tar::Archive::new(decoder(BufReader::new(reader), format)?)),
+        })
+    }
+}
+
+/// Undoes the compression wrapped around the tar stream.
+fn decoder<'a, R: BufRead + 'a>(input: R, format: Format) -> io::Result<Box<dyn Read + 'a>> {
+    let reader: Box<dyn Read> = match format {
+        Format::TarGz => Box::new(flate2::bufread::MultiGzDecoder::new(input)),
+        Format::TarBz2 => Box::new(bzip2::bufread::MultiBzDecoder::new(input)),
+        Format::TarXz => Box::new(xz2::bufread::XzDecoder::new_multi_decoder(input)),
+        Format::TarZst => Box::new(zstd::stream::read::Decoder::with_buffer(input)?),
+        Format::Tar | Format::Zip => Box::new(input),
+    };
+    Ok(reader)
+}
+
+/// Describes an entry, or returns `None` for kinds that are not extracted.
+fn metadata<R: Read>(index: usize, entry: &tar::Entry<R>) -> Option<Entry> {
+    let kind = file_kind(entry)?;
+    let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+    let link_target = entry
+        .link_name_bytes()
+        .map(|target| PathBuf::from(String::from_utf8_lossy(&target).into_owned()));
+    let modified = entry
+        .header()
+        .mtime()
+        .ok()
+        .and_then(|mtime| OffsetDateTime::from_unix_timestamp(mtime as i64).ok());
+    let unix_mode = entry
+        .header()
+        .mode()
+        .ok()
+        .map(|mode| mode & 0o7777 | file_type(&kind));
+    Some(Entry {
+        index,
+        enclosed_name: enclosed_name(&name),
+        name,
+        kind,
+        link_target,
+        size: entry.size(),
+        compressed_size: None,
+        method: "Stored".to_string(),
+        modified,
+        unix_mode,
+        crc32: None,
+        encryption: None,
+    })
+}
+
+/// The `S_IFMT` bits for a kind, which tar keeps in the entry type rather
+/// than the mode. Hard links are regular files once extracted.
+fn file_type(kind: &FileKind) -> u32 {
+    match kind {
+        FileKind::Directory => S_IFDIR,
+        FileKind::Symlink => S_IFLNK,
+        FileKind::File { .. } | FileKind::Hardlink => S_IFREG,
+    }
+}
+
+fn file_kind<R: Read>(entry: &tar::Entry<R>) -> Option<FileKind> {
+    match entry.header().entry_type() {
+        EntryType::Directory => Some(FileKind::Directory),
+        EntryType::Regular | EntryType::Continuous => Some(FileKind::File { size: entry.size() }),
+        EntryType::Symlink => Some(FileKind::Symlink),
+        EntryType::Link => Some(FileKind::Hardlink),
+        _ => None,
+    }
+}
+
+impl ArchiveBackend for TarBackend {
+    fn entries(&mut self) -> Result<Vec<Entry>, ExtractError> {
+        let mut entries = Vec::new();
+        let mut archive = self.archive()?;
+        for (index, entry) in archive.entries()?.enumerate() {
+            let entry = entry.map_err(decode_error)?;
+            if let Some(metadata) = metadata(index, &entry) {
+                entries.push(metadata);
+            }
+        }
+        Ok(entries)
+    }
+
+    fn read_entries(
+        &mut self,
+        indices: &[usize],
+        visit: &mut VisitEntry<'_>,
+    ) -> Result<(), ExtractError> {
+        let mut wanted = indices.iter().peekable();
+        let mut archive = self.archive()?;
+        for (index, entry) in archive.entries()?.enumerate() {
+            if wanted.peek().is_none() {
+                break;
+            }
+            let mut entry = entry.map_err(decode_error)?;
+            if wanted.next_if_eq(&&index).is_some() {
+                visit(index, Ok(&mut Decoded(&mut entry)))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ArchiveBackend for TarStreamBackend {
+    fn entries(&mut self) -> Result<Vec<Entry>, ExtractError> {
+        let mut entries = Vec::new();
+        self.stream_entries(&mut |entry, _| {
+            entries.push(entry.clone());
+            Ok(())
+        })?;
+        Ok(entries)
+    }
+
+    fn read_entries(
+        &mut self,
+        _indices: &[usize],
+        _visit: &mut VisitEntry<'_>,
+    ) -> Result<(), ExtractError> {
+        Err(not_seekable())
+    }
+
+    fn stream_entries(&mut self, visit: &mut StreamVisit<'_>) -> Result<(), ExtractError> {
+        let mut archive = self.archive.take().ok_or_else(not_seekable)?;
+        for (index, entry) in archive.entries()?.enumerate() {
+            let mut entry = entry.map_err(decode_error)?;
+            if let Some(metadata) = metadata(index, &entry) {
+                visit(&metadata, Ok(&mut Decoded(&mut entry)))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn modes_include_the_file_type() {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (entry_type, name, mode) in [
+            (EntryType::Directory, "dir/", 0o755),
+            (EntryType::Regular, "dir/file", 0o644),
+            (EntryType::Symlink, "dir/link", 0o777),
+            (EntryType::Link, "dir/hard", 0o644),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(entry_type);
+            header.set_mode(mode);
+            header.set_size(0);
+            if entry_type.is_symlink() || entry_type.is_hard_link() {
+                header.set_link_name("file").unwrap();
+            }
+            builder.append_data(&mut header, name, io::empty()).unwrap();
+        }
+        let bytes = builder.into_inner().unwrap();
+
+        let entries = TarStreamBackend::new(Box::new(Cursor::new(bytes)), Format::Tar)
+            .unwrap()
+            .entries()
+            .unwrap();
+        let modes: Vec<_> = entries.iter().map(|entry| entry.unix_mode).collect();
+        assert_eq!(
+            modes,
+            [
+                Some(0o040755),
+                Some(0o100644),
+                Some(0o120777),
+                Some(0o100644)
+            ]
+        );
+    }
+}
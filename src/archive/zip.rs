@@ -0,0 +1,522 @@
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+use deflate64::Deflate64Decoder;
+use time::OffsetDateTime;
+use zip::result::{InvalidPassword, ZipError};
+
+use super::shared_file::SharedFile;
+use super::{decode_error, ArchiveBackend, Encryption, Entry, ReadVisit, VisitEntry};
+use crate::attributes;
+use crate::crc::CrcReader;
+use crate::error::ExtractError;
+use crate::extractor::FileKind;
+
+/// General purpose flag bits that mark encrypted entries.
+const FLAG_ENCRYPTED: u16 = 1;
+const FLAG_STRONG_ENCRYPTION: u16 = 1 << 6;
+/// General purpose flag bit of LZMA entries whose data ends in an end of
+/// stream marker.
+const FLAG_LZMA_END_MARKER: u16 = 1 << 1;
+
+pub(super) const METHOD_STORED: u16 = 0;
+pub(super) const METHOD_DEFLATED: u16 = 8;
+pub(super) const METHOD_DEFLATE64: u16 = 9;
+pub(super) const METHOD_BZIP2: u16 = 12;
+pub(super) const METHOD_LZMA: u16 = 14;
+pub(super) const METHOD_ZSTD: u16 = 93;
+pub(super) const METHOD_XZ: u16 = 95;
+pub(super) const METHOD_AES: u16 = 99;
+const AES_EXTRA_FIELD: u16 = 0x9901;
+const EXTENDED_TIMESTAMP_EXTRA_FIELD: u16 = 0x5455;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+/// Longest symlink target read from an entry; anything longer is not a path.
+const MAX_LINK_TARGET: u64 = 4096;
+
+#[derive(Clone)]
+pub(crate) struct ZipBackend {
+    archive: zip::ZipArchive<SharedFile>,
+    /// Encryption of each entry. The zip crate keeps this to itself, so it is
+    /// read from the central directory separately.
+    encryption: Vec<Option<Encryption>>,
+    /// General purpose flags of each entry, also read separately.
+    flags: Vec<u16>,
+    /// Compression method of each entry; for AES encrypted ones, the method
+    /// of the data inside the encryption.
+    methods: Vec<u16>,
+    /// Whether each entry is AES encrypted in the AE-2 format, which stores no
+    /// CRC-32.
+    ae2: Vec<bool>,
+    password: Option<Vec<u8>>,
+}
+
+impl ZipBackend {
+    pub fn new(file: File) -> Result<Self, ExtractError> {
+        let file = SharedFile::new(file);
+        let mut headers = file.clone();
+        let mut archive = zip::ZipArchive::new(file)?;
+        let mut encryption = Vec::with_capacity(archive.len());
+        let mut flags = Vec::with_capacity(archive.len());
+        let mut methods = Vec::with_capacity(archive.len());
+        let mut ae2 = Vec::with_capacity(archive.len());
+        for index in 0..archive.len() {
+            let file = archive.by_index_raw(index)?;
+            let aes = aes_extra_field(file.extra_data());
+            let (entry_flags, method) = read_flags(&mut headers, file.central_header_start())?;
+            encryption.push(entry_encryption(entry_flags, method, aes));
+            flags.push(entry_flags);
+            methods.push(match method {
+                METHOD_AES => aes_method(file.extra_data()).unwrap_or(method),
+                method => method,
+            });
+            ae2.push(aes.is_some_and(|(vendor_version, _)| vendor_version == 2));
+        }
+        Ok(Self {
+            archive,
+            encryption,
+            flags,
+            methods,
+            ae2,
+            password: None,
+        })
+    }
+
+    /// Opens the data of an entry, decompressed by the zip crate or, for the
+    /// methods it lacks, here.
+    fn open(&mut self, index: usize) -> Result<ChecksumReader<'_>, ExtractError> {
+        match (self.methods[index], self.encryption[index]) {
+            (METHOD_STORED | METHOD_DEFLATED | METHOD_BZIP2 | METHOD_ZSTD, _)
+            | (_, Some(Encryption::Unsupported)) => {
+                self.open_zip_file(index).map(ChecksumReader::new)
+            }
+            (METHOD_DEFLATE64 | METHOD_LZMA | METHOD_XZ, None) => self.decode(index),
+            (method, encryption) => Err(ExtractError::UnsupportedMethod {
+                method,
+                encrypted: encryption.is_some(),
+            }),
+        }
+    }
+
+    fn open_zip_file(&mut self, index: usize) -> Result<zip::read::ZipFile<'_>, ExtractError> {
+        match (self.encryption[index], &self.password) {
+            (None, _) => Ok(self.archive.by_index(index)?),
+            (Some(Encryption::Unsupported), _) => Err(ExtractError::UnsupportedEncryption),
+            (Some(_), None) => Err(ExtractError::PasswordRequired),
+            (Some(_), Some(password)) => match self.archive.by_index_decrypt(index, password)? {
+                Ok(file) => Ok(file),
+                Err(InvalidPassword) => Err(ExtractError::WrongPassword),
+            },
+        }
+    }
+
+    /// Decodes the raw data of an entry compressed with Deflate64, LZMA or
+    /// XZ, which the zip crate cannot decompress.
+    fn decode(&mut self, index: usize) -> Result<ChecksumReader<'_>, ExtractError> {
+        let flags = self.flags[index];
+        let method = self.methods[index];
+        let file = self.archive.by_index_raw(index)?;
+        let (crc32, size) = (file.crc32(), file.size());
+        let data = io::BufReader::new(file);
+        let decoder: Box<dyn Read + '_> = match method {
+            METHOD_DEFLATE64 => Box::new(Deflate64Decoder::with_buffer(data)),
+            METHOD_LZMA => Box::new(LzmaDecoder::lzma(data, flags, Some(size))?),
+            _ => Box::new(LzmaDecoder::xz(data)?),
+        };
+        Ok(ChecksumReader::decoded(decoder, crc32, size))
+    }
+
+    /// Looks the entry up with the archive's name index and hands its data to
+    /// `visit`.
+    fn visit_by_name(&mut self, name: &str, visit: &mut ReadVisit<'_>) -> Result<(), ExtractError> {
+        let file = match &self.password {
+            Some(password) => self.archive.by_name_decrypt(name, password),
+            None => self.archive.by_name(name).map(Ok),
+        };
+        let file = match file {
+            Ok(Ok(file)) => file,
+            Ok(Err(InvalidPassword)) => return Err(ExtractError::WrongPassword),
+            Err(ZipError::FileNotFound) => {
+                return Err(ExtractError::EntryNotFound(name.to_string()))
+            }
+            Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)) => {
+                return Err(ExtractError::PasswordRequired)
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if file.is_dir() {
+            return Err(ExtractError::IsDirectory(name.to_string()));
+        }
+        visit(&mut ChecksumReader::new(file))
+    }
+
+    /// Looks the entry up by going through them all, for those the zip crate
+    /// refuses to hand out by name.
+    fn visit_by_position(
+        &mut self,
+        name: &str,
+        visit: &mut ReadVisit<'_>,
+    ) -> Result<(), ExtractError> {
+        let index = (0..self.archive.len())
+            .find(|&index| {
+                self.archive
+                    .by_index_raw(index)
+                    .is_ok_and(|file| file.name() == name)
+            })
+            .ok_or_else(|| ExtractError::EntryNotFound(name.to_string()))?;
+        let mut reader = self.open(index).map_err(|err| err.in_entry(name, None))?;
+        visit(&mut reader)
+    }
+
+    /// Whether `name` is stored as a directory, with a trailing slash.
+    fn has_directory(&self, name: &str) -> bool {
+        let dir_name = format!("{}/", name);
+        self.archive.file_names().any(|name| name == dir_name)
+    }
+
+    /// Reads the target a symlink entry stores as its data. Fails quietly if
+    /// the entry cannot be read, e.g. without the password, leaving the
+    /// extractor to read it while writing.
+    fn link_target(&mut self, index: usize) -> Option<PathBuf> {
+        let mut target = Vec::new();
+        self.open(index)
+            .ok()?
+            .take(MAX_LINK_TARGET)
+            .read_to_end(&mut target)
+            .ok()?;
+        Some(PathBuf::from(String::from_utf8_lossy(&target).into_owned()))
+    }
+}
+
+/// Reads an entry, turning the zip crate's bare "Invalid checksum" error at
+/// its end into invalid data naming both CRC-32 values, as the stream reader
+/// reports it. Entries decoded here are checked at their end by this reader
+/// itself.
+struct ChecksumReader<'a> {
+    inner: CrcReader<Box<dyn Read + 'a>>,
+    expected_crc32: u32,
+    size: u64,
+    /// Whether the CRC-32 and size are left to check at the end, as the zip
+    /// crate only checks the entries it decompresses.
+    check_end: bool,
+}
+
+impl<'a> ChecksumReader<'a> {
+    fn new(file: zip::read::ZipFile<'a>) -> Self {
+        Self {
+            expected_crc32: file.crc32(),
+            size: file.size(),
+            inner: CrcReader::new(Box::new(file)),
+            check_end: false,
+        }
+    }
+
+    fn decoded(decoder: Box<dyn Read + 'a>, crc32: u32, size: u64) -> Self {
+        Self {
+            inner: CrcReader::new(decoder),
+            expected_crc32: crc32,
+            size,
+            check_end: true,
+        }
+    }
+
+    fn crc_mismatch(&self) -> Option<io::Error> {
+        let crc32 = self.inner.crc32();
+        (self.inner.len() == self.size && crc32 != self.expected_crc32).then(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "CRC-32 mismatch: expected {:08x}, got {:08x}",
+                    self.expected_crc32, crc32
+                ),
+            )
+        })
+    }
+
+    fn check_end(&self) -> io::Result<()> {
+        if let Some(err) = self.crc_mismatch() {
+            return Err(err);
+        }
+        if self.inner.len() != self.size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "size mismatch: expected {} bytes, got {}",
+                    self.size,
+                    self.inner.len()
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Read for ChecksumReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner.read(buf) {
+            Ok(0) if self.check_end && !buf.is_empty() => self.check_end().map(|()| 0),
+            Ok(count) => Ok(count),
+            Err(err) => Err(self.crc_mismatch().unwrap_or_else(|| decode_error(err))),
+        }
+    }
+}
+
+/// LZMA data in a zip file starts with the version of the LZMA SDK that
+/// wrote it and the length of the properties that follow, then the raw
+/// stream.
+const LZMA_HEADER_LEN: usize = 4;
+const LZMA_PROPERTIES_LEN: usize = 5;
+/// The properties, then the size, in the header of the `.lzma` format.
+const LZMA_ALONE_HEADER_LEN: usize = LZMA_PROPERTIES_LEN + 8;
+
+/// Decodes LZMA or XZ data with liblzma. Unlike the reader of the xz2 crate
+/// it stops at the end of the stream instead of expecting the input to end
+/// there, as an entry may have a data descriptor after its data.
+pub(super) struct LzmaDecoder<R> {
+    /// The data, after a header of the `.lzma` format for LZMA data.
+    data: io::Chain<io::Cursor<Vec<u8>>, R>,
+    stream: xz2::stream::Stream,
+    done: bool,
+}
+
+impl<R: BufRead> LzmaDecoder<R> {
+    pub fn xz(data: R) -> io::Result<Self> {
+        Ok(Self {
+            data: io::Cursor::new(Vec::new()).chain(data),
+            stream: xz2::stream::Stream::new_stream_decoder(u64::MAX, 0)?,
+            done: false,
+        })
+    }
+
+    /// Reads the header of LZMA data, to hand liblzma the one of the `.lzma`
+    /// format instead. The size, if known, is only relied on when
+    /// the data has no end marker, as the decoder then has nothing else
+    /// telling where to stop.
+    pub fn lzma(mut data: R, flags: u16, size: Option<u64>) -> io::Result<Self> {
+        let mut header = [0; LZMA_HEADER_LEN];
+        data.read_exact(&mut header)?;
+        let properties_len = u16::from_le_bytes([header[2], header[3]]);
+        if usize::from(properties_len) != LZMA_PROPERTIES_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected LZMA properties length {}", properties_len),
+            ));
+        }
+        let mut alone_header = vec![0; LZMA_ALONE_HEADER_LEN];
+        data.read_exact(&mut alone_header[..LZMA_PROPERTIES_LEN])?;
+        let size = match size {
+            Some(size) if flags & FLAG_LZMA_END_MARKER == 0 => size,
+            _ => u64::MAX,
+        };
+        alone_header[LZMA_PROPERTIES_LEN..].copy_from_slice(&size.to_le_bytes());
+        Ok(Self {
+            data: io::Cursor::new(alone_header).chain(data),
+            stream: xz2::stream::Stream::new_lzma_decoder(u64::MAX)?,
+            done: false,
+        })
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        self.data.get_mut().1
+    }
+}
+
+impl<R: BufRead> Read for LzmaDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while !self.done && !buf.is_empty() {
+            let input = self.data.fill_buf()?;
+            let eof = input.is_empty();
+            let action = if eof {
+                xz2::stream::Action::Finish
+            } else {
+                xz2::stream::Action::Run
+            };
+            let (total_in, total_out) = (self.stream.total_in(), self.stream.total_out());
+            let status = self.stream.process(input, buf, action)?;
+            let consumed = (self.stream.total_in() - total_in) as usize;
+            let read = (self.stream.total_out() - total_out) as usize;
+            self.data.consume(consumed);
+            self.done = status == xz2::stream::Status::StreamEnd;
+            if read > 0 {
+                return Ok(read);
+            }
+            if eof && !self.done {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "compressed data ended early",
+                ));
+            }
+            if consumed == 0 && !self.done {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "corrupt LZMA data",
+                ));
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// Names a compression method by its id, as the zip crate names those it
+/// knows.
+pub(super) fn method_name(method: u16) -> String {
+    match method {
+        METHOD_STORED => "Stored".to_string(),
+        METHOD_DEFLATED => "Deflated".to_string(),
+        METHOD_DEFLATE64 => "Deflate64".to_string(),
+        METHOD_BZIP2 => "Bzip2".to_string(),
+        METHOD_LZMA => "Lzma".to_string(),
+        METHOD_ZSTD => "Zstd".to_string(),
+        METHOD_XZ => "Xz".to_string(),
+        METHOD_AES => "Aes".to_string(),
+        method => format!("Unsupported({})", method),
+    }
+}
+
+/// Reads the flags and method of the central directory header at `offset`.
+fn read_flags(file: &mut SharedFile, offset: u64) -> Result<(u16, u16), ExtractError> {
+    let mut header = [0; 12];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut header)?;
+    let flags = u16::from_le_bytes([header[8], header[9]]);
+    let method = u16::from_le_bytes([header[10], header[11]]);
+    Ok((flags, method))
+}
+
+fn entry_encryption(flags: u16, method: u16, aes: Option<(u16, u8)>) -> Option<Encryption> {
+    if flags & FLAG_ENCRYPTED == 0 {
+        None
+    } else if flags & FLAG_STRONG_ENCRYPTION != 0 {
+        Some(Encryption::Unsupported)
+    } else if method == METHOD_AES {
+        match aes {
+            Some((_, 1)) => Some(Encryption::Aes { bits: 128 }),
+            Some((_, 2)) => Some(Encryption::Aes { bits: 192 }),
+            Some((_, 3)) => Some(Encryption::Aes { bits: 256 }),
+            _ => Some(Encryption::Unsupported),
+        }
+    } else {
+        Some(Encryption::ZipCrypto)
+    }
+}
+
+/// Finds the data of the extra field with the given header id.
+pub(super) fn extra_field(mut extra: &[u8], wanted: u16) -> Option<&[u8]> {
+    while extra.len() >= 4 {
+        let id = u16::from_le_bytes([extra[0], extra[1]]);
+        let len = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+        let data = extra.get(4..4 + len)?;
+        if id == wanted {
+            return Some(data);
+        }
+        extra = &extra[4 + len..];
+    }
+    None
+}
+
+/// Returns the vendor version and key strength of the WinZip AES extra field.
+pub(super) fn aes_extra_field(extra: &[u8]) -> Option<(u16, u8)> {
+    let data = extra_field(extra, AES_EXTRA_FIELD).filter(|data| data.len() >= 5)?;
+    Some((u16::from_le_bytes([data[0], data[1]]), data[4]))
+}
+
+/// Returns the compression method of the data the WinZip AES extra field
+/// encrypts.
+fn aes_method(extra: &[u8]) -> Option<u16> {
+    let data = extra_field(extra, AES_EXTRA_FIELD)?;
+    Some(u16::from_le_bytes(data.get(5..7)?.try_into().ok()?))
+}
+
+/// Returns the modification time of the extended timestamp extra field,
+/// which unlike the basic field is in UTC with one-second precision.
+pub(super) fn extended_mtime(extra: &[u8]) -> Option<OffsetDateTime> {
+    let data = extra_field(extra, EXTENDED_TIMESTAMP_EXTRA_FIELD)?;
+    let flags = *data.first()?;
+    if flags & 1 == 0 {
+        return None;
+    }
+    let mtime = i32::from_le_bytes(data.get(1..5)?.try_into().ok()?);
+    OffsetDateTime::from_unix_timestamp(i64::from(mtime)).ok()
+}
+
+impl ArchiveBackend for ZipBackend {
+    fn entries(&mut self) -> Result<Vec<Entry>, ExtractError> {
+        let mut entries = Vec::with_capacity(self.archive.len());
+        for index in 0..self.archive.len() {
+            let file = self.archive.by_index_raw(index)?;
+            let kind = if file.is_dir() {
+                FileKind::Directory
+            } else if file
+                .unix_mode()
+                .is_some_and(|mode| mode & S_IFMT == S_IFLNK)
+            {
+                FileKind::Symlink
+            } else {
+                FileKind::File { size: file.size() }
+            };
+            let mut entry = Entry {
+                index,
+                name: file.name().to_string(),
+                enclosed_name: file.enclosed_name().map(|path| path.to_path_buf()),
+                kind,
+                link_target: None,
+                size: file.size(),
+                compressed_size: Some(file.compressed_size()),
+                method: method_name(self.methods[index]),
+                modified: extended_mtime(file.extra_data())
+                    .or_else(|| attributes::dos_time_to_utc(file.last_modified())),
+                unix_mode: file.unix_mode(),
+                crc32: if self.ae2[index] {
+                    None
+                } else {
+                    Some(file.crc32())
+                },
+                encryption: self.encryption[index],
+            };
+            drop(file);
+            if kind == FileKind::Symlink {
+                entry.link_target = self.link_target(index);
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    fn read_entries(
+        &mut self,
+        indices: &[usize],
+        visit: &mut VisitEntry<'_>,
+    ) -> Result<(), ExtractError> {
+        for &index in indices {
+            match self.open(index) {
+                Ok(mut file) => visit(index, Ok(&mut file))?,
+                Err(err) => visit(index, Err(err))?,
+            }
+        }
+        Ok(())
+    }
+
+    fn read_by_name(&mut self, name: &str, visit: &mut ReadVisit<'_>) -> Result<(), ExtractError> {
+        match self.visit_by_name(name, visit) {
+            Err(ExtractError::EntryNotFound(_)) if self.has_directory(name) => {
+                Err(ExtractError::IsDirectory(name.to_string()))
+            }
+            // The zip crate refuses methods it cannot decompress before
+            // handing out the entry.
+            Err(ExtractError::ZipError(ZipError::UnsupportedArchive(_))) => {
+                self.visit_by_position(name, visit)
+            }
+            result => result,
+        }
+    }
+
+    fn set_password(&mut self, password: Option<Vec<u8>>) {
+        self.password = password;
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn ArchiveBackend + Send>> {
+        Some(Box::new(self.clone()))
+    }
+}
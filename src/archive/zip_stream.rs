@@ -0,0 +1,792 @@
+//! Reads zip archives front to back by walking the local file headers, for
+//! input that cannot seek to the central directory.
+//!
+//! The local headers carry no Unix mode, so permissions are not restored and
+//! symlinks come out as regular files holding the target path.
+
+use std::io::{self, BufRead, Read, Take};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use deflate64::Deflate64Decoder;
+
+use super::zip::{
+    aes_extra_field, extended_mtime, extra_field, method_name, LzmaDecoder, METHOD_AES,
+    METHOD_BZIP2, METHOD_DEFLATE64, METHOD_DEFLATED, METHOD_LZMA, METHOD_STORED, METHOD_XZ,
+    METHOD_ZSTD,
+};
+use super::{
+    decode_error, enclosed_name, not_seekable, ArchiveBackend, Encryption, Entry, StreamVisit,
+    VisitEntry,
+};
+use crate::attributes;
+use crate::crc::CrcReader;
+use crate::error::ExtractError;
+use crate::extractor::FileKind;
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const DATA_DESCRIPTOR: u32 = 0x08074b50;
+const ZIP64_EXTRA_FIELD: u16 = 0x0001;
+
+const FLAG_ENCRYPTED: u16 = 1;
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+
+/// Buffered input that can look further ahead than it has handed out, which
+/// finding the end of stored entries with a data descriptor needs.
+struct Source {
+    inner: Box<dyn Read>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl Source {
+    const CHUNK: usize = 8192;
+
+    /// Returns the next `len` bytes without consuming them, or fewer at the
+    /// end of the stream.
+    fn peek(&mut self, len: usize) -> io::Result<&[u8]> {
+        if self.buf.len() - self.pos < len {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+            while self.buf.len() < len {
+                let start = self.buf.len();
+                self.buf.resize(start + Self::CHUNK, 0);
+                let count = match self.inner.read(&mut self.buf[start..]) {
+                    Ok(count) => count,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+                        self.buf.truncate(start);
+                        continue;
+                    }
+                    Err(err) => {
+                        self.buf.truncate(start);
+                        return Err(err);
+                    }
+                };
+                self.buf.truncate(start + count);
+                if count == 0 {
+                    break;
+                }
+            }
+        }
+        let end = self.buf.len().min(self.pos + len);
+        Ok(&self.buf[self.pos..end])
+    }
+}
+
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+impl BufRead for Source {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.buf.len() {
+            self.buf.clear();
+            self.pos = 0;
+            self.peek(Self::CHUNK)?;
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.buf.len());
+    }
+}
+
+pub(crate) struct ZipStreamBackend {
+    /// `None` once the stream has been walked.
+    source: Option<Source>,
+    /// Stored bytes read of the current entry, which entries with a data
+    /// descriptor only declare after them.
+    compressed_read: Arc<AtomicU64>,
+}
+
+impl ZipStreamBackend {
+    pub fn new(reader: Box<dyn Read>) -> Self {
+        Self {
+            source: Some(Source {
+                inner: reader,
+                buf: Vec::new(),
+                pos: 0,
+            }),
+            compressed_read: Arc::default(),
+        }
+    }
+
+    /// Hands every entry to `visit` and returns them with the sizes and
+    /// CRC-32 found while reading their data, which entries written with a
+    /// data descriptor only record after it.
+    fn walk(&mut self, visit: &mut StreamVisit<'_>) -> Result<Vec<Entry>, ExtractError> {
+        let mut source = self.source.take().ok_or_else(not_seekable)?;
+        let mut entries = Vec::new();
+        loop {
+            match read_u32(&mut source) {
+                Ok(LOCAL_HEADER) => (),
+                Ok(CENTRAL_HEADER | END_OF_CENTRAL_DIRECTORY) => {
+                    // Read the central directory too, so whatever writes into
+                    // the pipe does not fail on a closed reader.
+                    io::copy(&mut source, &mut io::sink())?;
+                    break;
+                }
+                // Only the central directory tells that no entries follow;
+                // a stream ending before it was cut off.
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "stream ended before the central directory",
+                    )
+                    .into())
+                }
+                Ok(signature) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("expected a zip local file header, found {:08x}", signature),
+                    )
+                    .into())
+                }
+                Err(err) => return Err(err.into()),
+            }
+            let header = LocalHeader::read(&mut source)?;
+            let mut entry = header.entry(entries.len());
+
+            if let Err(err) = header.readable() {
+                visit(&entry, Err(err))?;
+                if header.has_descriptor() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("cannot find where {} ends in the stream", entry.name),
+                    )
+                    .into());
+                }
+                io::copy(
+                    &mut (&mut source).take(header.compressed_size),
+                    &mut io::sink(),
+                )?;
+                entries.push(entry);
+                continue;
+            }
+
+            let mut reader =
+                EntryReader::new(&mut source, &header, Arc::clone(&self.compressed_read))?;
+            visit(&entry, Ok(&mut reader))?;
+            // Whatever the visitor left unread still has to be consumed to
+            // reach the next header, and is checked along the way.
+            io::copy(&mut reader, &mut io::sink())?;
+            let (size, compressed_size, crc32) = reader.totals;
+            entry.size = size;
+            entry.kind = match entry.kind {
+                FileKind::File { .. } => FileKind::File { size },
+                kind => kind,
+            };
+            entry.compressed_size = Some(compressed_size);
+            entry.crc32 = Some(crc32);
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+}
+
+impl ArchiveBackend for ZipStreamBackend {
+    fn entries(&mut self) -> Result<Vec<Entry>, ExtractError> {
+        self.walk(&mut |_, _| Ok(()))
+    }
+
+    fn read_entries(
+        &mut self,
+        _indices: &[usize],
+        _visit: &mut VisitEntry<'_>,
+    ) -> Result<(), ExtractError> {
+        Err(not_seekable())
+    }
+
+    fn stream_entries(&mut self, visit: &mut StreamVisit<'_>) -> Result<(), ExtractError> {
+        self.walk(visit).map(drop)
+    }
+
+    fn compressed_read(&self) -> Option<Arc<AtomicU64>> {
+        Some(Arc::clone(&self.compressed_read))
+    }
+}
+
+struct LocalHeader {
+    flags: u16,
+    method: u16,
+    time: u16,
+    date: u16,
+    crc32: u32,
+    compressed_size: u64,
+    size: u64,
+    name: String,
+    extra: Vec<u8>,
+    zip64: bool,
+}
+
+impl LocalHeader {
+    /// Reads the header that follows the signature.
+    fn read(source: &mut Source) -> io::Result<Self> {
+        let mut fixed = [0; 26];
+        source.read_exact(&mut fixed)?;
+        let u16_at = |at: usize| u16::from_le_bytes([fixed[at], fixed[at + 1]]);
+        let u32_at = |at: usize| u32::from_le_bytes(fixed[at..at + 4].try_into().unwrap());
+
+        let mut name = vec![0; u16_at(22) as usize];
+        source.read_exact(&mut name)?;
+        let mut extra = vec![0; u16_at(24) as usize];
+        source.read_exact(&mut extra)?;
+
+        let mut size = u64::from(u32_at(18));
+        let mut compressed_size = u64::from(u32_at(14));
+        let zip64 = extra_field(&extra, ZIP64_EXTRA_FIELD);
+        if let Some(mut zip64) = zip64 {
+            for field in [&mut size, &mut compressed_size] {
+                if *field == u64::from(u32::MAX) && zip64.len() >= 8 {
+                    *field = u64::from_le_bytes(zip64[..8].try_into().unwrap());
+                    zip64 = &zip64[8..];
+                }
+            }
+        }
+        Ok(Self {
+            flags: u16_at(2),
+            method: u16_at(4),
+            time: u16_at(6),
+            date: u16_at(8),
+            crc32: u32_at(10),
+            compressed_size,
+            size,
+            name: String::from_utf8_lossy(&name).into_owned(),
+            zip64: zip64.is_some(),
+            extra,
+        })
+    }
+
+    fn has_descriptor(&self) -> bool {
+        self.flags & FLAG_DATA_DESCRIPTOR != 0
+    }
+
+    fn encryption(&self) -> Option<Encryption> {
+        if self.flags & FLAG_ENCRYPTED == 0 {
+            return None;
+        }
+        Some(match (self.method, aes_extra_field(&self.extra)) {
+            (METHOD_AES, Some((_, 1))) => Encryption::Aes { bits: 128 },
+            (METHOD_AES, Some((_, 2))) => Encryption::Aes { bits: 192 },
+            (METHOD_AES, Some((_, 3))) => Encryption::Aes { bits: 256 },
+            (METHOD_AES, _) => Encryption::Unsupported,
+            _ => Encryption::ZipCrypto,
+        })
+    }
+
+    /// Sizes and CRC-32 of entries with a data descriptor are unknown until
+    /// their data has been read.
+    fn entry(&self, index: usize) -> Entry {
+        let known = !self.has_descriptor();
+        let kind = if self.name.ends_with('/') {
+            FileKind::Directory
+        } else {
+            FileKind::File { size: self.size }
+        };
+        Entry {
+            index,
+            name: self.name.clone(),
+            enclosed_name: enclosed_name(&self.name),
+            kind,
+            link_target: None,
+            size: self.size,
+            compressed_size: known.then_some(self.compressed_size),
+            method: method_name(self.method),
+            modified: extended_mtime(&self.extra).or_else(|| {
+                attributes::dos_time_to_utc(zip::DateTime::from_msdos(self.date, self.time))
+            }),
+            unix_mode: None,
+            crc32: known.then_some(self.crc32),
+            encryption: self.encryption(),
+        }
+    }
+
+    /// Checks that the data can be decompressed without the central directory.
+    fn readable(&self) -> Result<(), ExtractError> {
+        if self.encryption().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "encrypted entries cannot be extracted from a stream",
+            )
+            .into());
+        }
+        match self.method {
+            METHOD_STORED | METHOD_DEFLATED | METHOD_DEFLATE64 | METHOD_BZIP2 | METHOD_LZMA
+            | METHOD_ZSTD | METHOD_XZ => Ok(()),
+            method => Err(ExtractError::UnsupportedMethod {
+                method,
+                encrypted: false,
+            }),
+        }
+    }
+}
+
+/// Compressed data of one entry. Without a data descriptor it is cut off at
+/// the compressed size; with one the decoder has to find the end itself.
+type Data<'a> = Take<&'a mut Source>;
+
+enum Decoder<'a> {
+    Stored(Data<'a>),
+    StoredUntilDescriptor(DescriptorScan<'a>),
+    Deflated(flate2::bufread::DeflateDecoder<Data<'a>>),
+    Deflate64(Deflate64Decoder<Data<'a>>),
+    Deflate64UntilDescriptor(Deflate64Decoder<ByteByByte<Data<'a>>>),
+    Bzip2(bzip2::bufread::BzDecoder<Data<'a>>),
+    Lzma(LzmaDecoder<Data<'a>>),
+    Zstd(zstd::stream::read::Decoder<'static, Data<'a>>),
+    Xz(LzmaDecoder<Data<'a>>),
+}
+
+impl<'a> Decoder<'a> {
+    fn new(data: Data<'a>, header: &LocalHeader) -> io::Result<Self> {
+        Ok(match header.method {
+            METHOD_STORED if header.has_descriptor() => {
+                Decoder::StoredUntilDescriptor(DescriptorScan::new(data, header.zip64))
+            }
+            METHOD_DEFLATED => Decoder::Deflated(flate2::bufread::DeflateDecoder::new(data)),
+            METHOD_DEFLATE64 if header.has_descriptor() => {
+                Decoder::Deflate64UntilDescriptor(Deflate64Decoder::with_buffer(ByteByByte(data)))
+            }
+            METHOD_DEFLATE64 => Decoder::Deflate64(Deflate64Decoder::with_buffer(data)),
+            METHOD_BZIP2 => Decoder::Bzip2(bzip2::bufread::BzDecoder::new(data)),
+            METHOD_LZMA => {
+                let size = (!header.has_descriptor()).then_some(header.size);
+                Decoder::Lzma(LzmaDecoder::lzma(data, header.flags, size)?)
+            }
+            METHOD_ZSTD => {
+                Decoder::Zstd(zstd::stream::read::Decoder::with_buffer(data)?.single_frame())
+            }
+            METHOD_XZ => Decoder::Xz(LzmaDecoder::xz(data)?),
+            _ => Decoder::Stored(data),
+        })
+    }
+
+    fn data(&mut self) -> &mut Data<'a> {
+        match self {
+            Decoder::Stored(data) => data,
+            Decoder::StoredUntilDescriptor(scan) => &mut scan.data,
+            Decoder::Deflated(decoder) => decoder.get_mut(),
+            Decoder::Deflate64(decoder) => decoder.get_mut(),
+            Decoder::Deflate64UntilDescriptor(decoder) => &mut decoder.get_mut().0,
+            Decoder::Bzip2(decoder) => decoder.get_mut(),
+            Decoder::Lzma(decoder) => decoder.get_mut(),
+            Decoder::Zstd(decoder) => decoder.get_mut(),
+            Decoder::Xz(decoder) => decoder.get_mut(),
+        }
+    }
+}
+
+impl Read for Decoder<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = match self {
+            Decoder::Stored(data) => data.read(buf),
+            Decoder::StoredUntilDescriptor(scan) => scan.read(buf),
+            Decoder::Deflated(decoder) => decoder.read(buf),
+            Decoder::Deflate64(decoder) => decoder.read(buf),
+            Decoder::Deflate64UntilDescriptor(decoder) => decoder.read(buf),
+            Decoder::Bzip2(decoder) => decoder.read(buf),
+            Decoder::Lzma(decoder) => decoder.read(buf),
+            Decoder::Zstd(decoder) => decoder.read(buf),
+            Decoder::Xz(decoder) => decoder.read(buf),
+        };
+        result.map_err(decode_error)
+    }
+}
+
+/// Hands out data one byte at a time. Given more, the Deflate64 decoder
+/// takes a few bytes past the end of the data into its bit buffer, which
+/// would swallow the start of a data descriptor.
+struct ByteByByte<R>(R);
+
+impl<R: BufRead> Read for ByteByByte<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.fill_buf()?.read(buf)?;
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+impl<R: BufRead> BufRead for ByteByByte<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let buf = self.0.fill_buf()?;
+        Ok(&buf[..buf.len().min(1)])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.0.consume(amt);
+    }
+}
+
+/// Stored data followed by a data descriptor has nothing marking its end but
+/// the descriptor itself, so it is read up to the first descriptor signature
+/// whose CRC-32 and sizes match the data before it.
+struct DescriptorScan<'a> {
+    data: Data<'a>,
+    zip64: bool,
+    hasher: crc32fast::Hasher,
+    len: u64,
+    done: bool,
+}
+
+impl<'a> DescriptorScan<'a> {
+    fn new(data: Data<'a>, zip64: bool) -> Self {
+        Self {
+            data,
+            zip64,
+            hasher: crc32fast::Hasher::new(),
+            len: 0,
+            done: false,
+        }
+    }
+}
+
+impl Read for DescriptorScan<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        let descriptor_len = if self.zip64 { 24 } else { 16 };
+        self.data.get_mut().peek(descriptor_len)?;
+        let ahead = self.data.fill_buf()?;
+        if ahead.len() < 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "stream ended inside an entry",
+            ));
+        }
+        let signature = DATA_DESCRIPTOR.to_le_bytes();
+        let count = match ahead.windows(4).position(|window| window == signature) {
+            Some(0)
+                if is_descriptor(ahead, self.hasher.clone().finalize(), self.len, self.zip64) =>
+            {
+                self.done = true;
+                return Ok(0);
+            }
+            // The signature bytes are part of the data after all.
+            Some(0) => 1,
+            Some(position) => position,
+            // The last bytes may be the start of a signature.
+            None => ahead.len() - 3,
+        }
+        .min(buf.len());
+        buf[..count].copy_from_slice(&ahead[..count]);
+        self.hasher.update(&buf[..count]);
+        self.len += count as u64;
+        self.data.consume(count);
+        Ok(count)
+    }
+}
+
+fn is_descriptor(ahead: &[u8], crc32: u32, len: u64, zip64: bool) -> bool {
+    let field = |at: usize, width: usize| {
+        ahead.get(at..at + width).map(|bytes| {
+            bytes
+                .iter()
+                .rev()
+                .fold(0u64, |value, &b| value << 8 | u64::from(b))
+        })
+    };
+    let width = if zip64 { 8 } else { 4 };
+    field(4, 4) == Some(u64::from(crc32))
+        && field(8, width) == Some(len)
+        && field(8 + width, width) == Some(len)
+}
+
+/// Decompresses one entry and, at its end, checks it against the CRC-32 and
+/// sizes from the local header or the data descriptor after the data.
+struct EntryReader<'a> {
+    decoder: CrcReader<Decoder<'a>>,
+    expected: Option<(u32, u64, u64)>,
+    descriptor: Option<bool>,
+    /// How many stored bytes the decoder may take, to tell how many it has.
+    limit: u64,
+    compressed_read: Arc<AtomicU64>,
+    /// Size, compressed size and CRC-32, once the end has been reached.
+    totals: (u64, u64, u32),
+    finished: bool,
+}
+
+impl<'a> EntryReader<'a> {
+    fn new(
+        source: &'a mut Source,
+        header: &LocalHeader,
+        compressed_read: Arc<AtomicU64>,
+    ) -> io::Result<Self> {
+        let limit = if header.has_descriptor() {
+            u64::MAX
+        } else {
+            header.compressed_size
+        };
+        compressed_read.store(0, Ordering::Relaxed);
+        Ok(Self {
+            decoder: CrcReader::new(Decoder::new(source.take(limit), header)?),
+            expected: (!header.has_descriptor()).then_some((
+                header.crc32,
+                header.compressed_size,
+                header.size,
+            )),
+            descriptor: header.has_descriptor().then_some(header.zip64),
+            limit,
+            compressed_read,
+            totals: (0, 0, 0),
+            finished: false,
+        })
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.finished = true;
+        let crc32 = self.decoder.crc32();
+        let size = self.decoder.len();
+        let data = self.decoder.get_mut().data();
+        let (expected_crc32, expected_compressed_size, expected_size) = match self.descriptor {
+            Some(zip64) => {
+                let compressed_size = u64::MAX - data.limit();
+                let descriptor = read_descriptor(data.get_mut(), zip64)?;
+                if descriptor.1 != compressed_size {
+                    return Err(invalid_data(format!(
+                        "compressed size mismatch: expected {} bytes, got {}",
+                        descriptor.1, compressed_size
+                    )));
+                }
+                descriptor
+            }
+            None => {
+                // Data the decoder did not need still belongs to this entry.
+                io::copy(data, &mut io::sink())?;
+                self.expected.unwrap()
+            }
+        };
+        if crc32 != expected_crc32 {
+            return Err(invalid_data(format!(
+                "CRC-32 mismatch: expected {:08x}, got {:08x}",
+                expected_crc32, crc32
+            )));
+        }
+        if size != expected_size {
+            return Err(invalid_data(format!(
+                "size mismatch: expected {} bytes, got {}",
+                expected_size, size
+            )));
+        }
+        self.totals = (size, expected_compressed_size, crc32);
+        Ok(())
+    }
+}
+
+impl Read for EntryReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.finished || buf.is_empty() {
+            return Ok(0);
+        }
+        let count = self.decoder.read(buf)?;
+        let left = self.decoder.get_mut().data().limit();
+        self.compressed_read
+            .store(self.limit - left, Ordering::Relaxed);
+        if count == 0 {
+            self.finish()?;
+        }
+        Ok(count)
+    }
+}
+
+/// Reads the CRC-32, compressed size and size that follow the data. The
+/// signature in front of them is optional.
+fn read_descriptor(source: &mut Source, zip64: bool) -> io::Result<(u32, u64, u64)> {
+    let mut crc32 = read_u32(source)?;
+    if crc32 == DATA_DESCRIPTOR {
+        crc32 = read_u32(source)?;
+    }
+    let (compressed_size, size) = if zip64 {
+        (read_u64(source)?, read_u64(source)?)
+    } else {
+        (u64::from(read_u32(source)?), u64::from(read_u32(source)?))
+    };
+    Ok((crc32, compressed_size, size))
+}
+
+fn read_u32(source: &mut Source) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    source.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(source: &mut Source) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    source.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::write::FileOptions;
+
+    use super::*;
+
+    fn archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for (name, data) in entries {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn names(reader: impl Read + 'static) -> Result<Vec<String>, ExtractError> {
+        let entries = ZipStreamBackend::new(Box::new(reader)).entries()?;
+        Ok(entries.into_iter().map(|entry| entry.name).collect())
+    }
+
+    /// Where the local header of the entry at `index` starts.
+    fn local_header(bytes: &[u8], index: usize) -> usize {
+        bytes
+            .windows(4)
+            .enumerate()
+            .filter(|(_, window)| *window == LOCAL_HEADER.to_le_bytes())
+            .nth(index)
+            .unwrap()
+            .0
+    }
+
+    /// Moves the CRC-32 and sizes of the entry at `index` from its local
+    /// header to a data descriptor after its data, as streaming writers leave
+    /// them.
+    fn move_to_descriptor(bytes: &mut Vec<u8>, index: usize) {
+        let header = local_header(bytes, index);
+        let field = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let (crc32, compressed_size, size) =
+            (field(header + 14), field(header + 18), field(header + 22));
+        let name_len = u16::from_le_bytes([bytes[header + 26], bytes[header + 27]]);
+        let extra_len = u16::from_le_bytes([bytes[header + 28], bytes[header + 29]]);
+        bytes[header + 6] |= FLAG_DATA_DESCRIPTOR as u8;
+        bytes[header + 14..header + 26].fill(0);
+        let data_end = header + 30 + usize::from(name_len + extra_len) + compressed_size as usize;
+        let descriptor = [DATA_DESCRIPTOR, crc32, compressed_size, size]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<_>>();
+        bytes.splice(data_end..data_end, descriptor);
+    }
+
+    fn contents(reader: impl Read + 'static) -> Result<Vec<(String, Vec<u8>)>, ExtractError> {
+        let mut contents = Vec::new();
+        ZipStreamBackend::new(Box::new(reader)).stream_entries(&mut |entry, reader| {
+            let mut data = Vec::new();
+            reader?.read_to_end(&mut data)?;
+            contents.push((entry.name.clone(), data));
+            Ok(())
+        })?;
+        Ok(contents)
+    }
+
+    fn error_kind(result: Result<Vec<(String, Vec<u8>)>, ExtractError>) -> io::ErrorKind {
+        match result {
+            Err(ExtractError::IoError(err)) => err.kind(),
+            result => panic!("expected an I/O error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn reads_up_to_the_central_directory() {
+        let bytes = archive(&[("a", b"first"), ("b", b"second")]);
+        assert_eq!(names(Cursor::new(bytes)).unwrap(), ["a", "b"]);
+    }
+
+    #[test]
+    fn stream_cut_off_between_entries_fails() {
+        let mut bytes = archive(&[("a", b"first"), ("b", b"second")]);
+        bytes.truncate(local_header(&bytes, 1));
+        match names(Cursor::new(bytes)) {
+            Err(ExtractError::IoError(err)) => {
+                assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof)
+            }
+            result => panic!("expected the end of the stream to fail, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn undecodable_data_is_corrupt() {
+        let mut bytes = archive(&[("a", b"\xff\xff\xff\xff")]);
+        // Mark the entry deflated, which its data is not: 0xff starts a block
+        // of the reserved type.
+        let method = local_header(&bytes, 0) + 8;
+        bytes[method..method + 2].copy_from_slice(&METHOD_DEFLATED.to_le_bytes());
+        match names(Cursor::new(bytes)) {
+            Err(ExtractError::IoError(err)) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+            result => panic!("expected the data to be corrupt, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn descriptor_signature_inside_stored_data_is_read_as_data() {
+        let data = b"abPK\x07\x08cdPK\x07\x08";
+        let mut bytes = archive(&[("a", data), ("b", b"second")]);
+        move_to_descriptor(&mut bytes, 0);
+        let contents = contents(Cursor::new(bytes)).unwrap();
+        assert_eq!(contents[0], ("a".to_string(), data.to_vec()));
+        assert_eq!(contents[1], ("b".to_string(), b"second".to_vec()));
+    }
+
+    #[test]
+    fn entry_cut_off_in_its_data_fails() {
+        let mut bytes = archive(&[("a", b"first entry")]);
+        bytes.truncate(local_header(&bytes, 0) + 30 + 1 + 5);
+        assert_eq!(
+            error_kind(contents(Cursor::new(bytes))),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn entry_cut_off_in_its_descriptor_fails() {
+        let mut bytes = archive(&[("a", b"first entry")]);
+        move_to_descriptor(&mut bytes, 0);
+        bytes.truncate(local_header(&bytes, 0) + 30 + 1 + 11 + 10);
+        assert_eq!(
+            error_kind(contents(Cursor::new(bytes))),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn interrupted_reads_are_retried() {
+        struct Interrupting {
+            inner: Cursor<Vec<u8>>,
+            interrupt: bool,
+        }
+
+        impl Read for Interrupting {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.interrupt = !self.interrupt;
+                if self.interrupt {
+                    return Err(io::ErrorKind::Interrupted.into());
+                }
+                // A few bytes at a time, for many interruptions.
+                let len = buf.len().min(7);
+                self.inner.read(&mut buf[..len])
+            }
+        }
+
+        let bytes = archive(&[("a", b"first"), ("b", b"second")]);
+        let reader = Interrupting {
+            inner: Cursor::new(bytes),
+            interrupt: false,
+        };
+        assert_eq!(names(reader).unwrap(), ["a", "b"]);
+    }
+}
@@ -0,0 +1,147 @@
+//! Restores the permissions and modification times recorded in an archive,
+//! and reads those of files being archived.
+
+use std::fs::Metadata;
+use std::io;
+use std::path::Path;
+
+use filetime::FileTime;
+use time::OffsetDateTime;
+
+/// Applies the permission bits of a Unix mode. Setuid, setgid and sticky bits
+/// are dropped so an archive cannot plant privileged executables.
+#[cfg(unix)]
+pub(crate) fn set_permissions(path: &Path, mode: u32) -> io::Result<()> {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn set_permissions(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// The mode of a file being archived, type bits included.
+#[cfg(unix)]
+pub(crate) fn unix_mode(metadata: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Some(metadata.permissions().mode())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn unix_mode(_metadata: &Metadata) -> Option<u32> {
+    None
+}
+
+pub(crate) fn set_modified(path: &Path, modified: OffsetDateTime) -> io::Result<()> {
+    filetime::set_file_mtime(path, file_time(modified))
+}
+
+/// Sets the modification time of a symlink itself rather than its target.
+pub(crate) fn set_link_modified(path: &Path, modified: OffsetDateTime) -> io::Result<()> {
+    let mtime = file_time(modified);
+    filetime::set_symlink_file_times(path, mtime, mtime)
+}
+
+#[cfg(unix)]
+pub(crate) fn create_symlink(target: &Path, path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn create_symlink(_target: &Path, _path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symlinks can only be extracted on Unix",
+    ))
+}
+
+/// Reads the date and time of the DOS fields of a zip entry, which zip tools
+/// write in local time.
+#[cfg(unix)]
+pub(crate) fn dos_time_to_utc(datetime: zip::DateTime) -> Option<OffsetDateTime> {
+    // Out of range fields are rejected rather than carried over by mktime.
+    datetime.to_time().ok()?;
+    // SAFETY: an all-zero `tm` is valid, and mktime only reads and
+    // normalizes the fields it is given.
+    let seconds = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        tm.tm_year = i32::from(datetime.year()) - 1900;
+        tm.tm_mon = i32::from(datetime.month()) - 1;
+        tm.tm_mday = i32::from(datetime.day());
+        tm.tm_hour = i32::from(datetime.hour());
+        tm.tm_min = i32::from(datetime.minute());
+        tm.tm_sec = i32::from(datetime.second());
+        // Let the time zone rules decide whether daylight saving applies.
+        tm.tm_isdst = -1;
+        libc::mktime(&mut tm)
+    };
+    if seconds == -1 {
+        return None;
+    }
+    // time_t is only 32 bits wide on some targets.
+    #[allow(clippy::useless_conversion)]
+    OffsetDateTime::from_unix_timestamp(i64::from(seconds)).ok()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn dos_time_to_utc(datetime: zip::DateTime) -> Option<OffsetDateTime> {
+    datetime.to_time().ok()
+}
+
+/// Converts a time to the local date and time the DOS fields of a zip entry
+/// hold, if it falls within their years, 1980 to 2107.
+#[cfg(unix)]
+pub(crate) fn local_dos_time(time: OffsetDateTime) -> Option<zip::DateTime> {
+    let seconds = libc::time_t::try_from(time.unix_timestamp()).ok()?;
+    // SAFETY: an all-zero `tm` is valid, and localtime_r writes only to it.
+    let tm = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&seconds, &mut tm).is_null() {
+            return None;
+        }
+        tm
+    };
+    zip::DateTime::from_date_and_time(
+        u16::try_from(tm.tm_year + 1900).ok()?,
+        u8::try_from(tm.tm_mon + 1).ok()?,
+        u8::try_from(tm.tm_mday).ok()?,
+        u8::try_from(tm.tm_hour).ok()?,
+        u8::try_from(tm.tm_min).ok()?,
+        // A leap second does not fit.
+        u8::try_from(tm.tm_sec.min(59)).ok()?,
+    )
+    .ok()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn local_dos_time(time: OffsetDateTime) -> Option<zip::DateTime> {
+    time.try_into().ok()
+}
+
+fn file_time(time: OffsetDateTime) -> FileTime {
+    FileTime::from_unix_time(time.unix_timestamp(), time.nanosecond())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dos_time_round_trips_through_local_time() {
+        // 2024-07-01 12:34:56 UTC, in summer time wherever it applies.
+        let time = OffsetDateTime::from_unix_timestamp(1_719_837_296).unwrap();
+        let dos_time = local_dos_time(time).unwrap();
+        assert_eq!(dos_time_to_utc(dos_time), Some(time));
+    }
+
+    #[test]
+    fn dos_time_outside_its_range_is_rejected() {
+        let before_1980 = OffsetDateTime::from_unix_timestamp(0).unwrap();
+        assert!(local_dos_time(before_1980).is_none());
+        assert!(dos_time_to_utc(zip::DateTime::from_msdos(0, 0)).is_none());
+    }
+}
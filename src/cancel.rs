@@ -0,0 +1,52 @@
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Asks a running extraction to stop, e.g. from a signal handler. Clones
+/// share the same state, so one can be handed to the extractor and another
+/// kept to cancel it.
+///
+/// Extraction stops after the chunk it is copying, removes the partly
+/// written file and fails with [`ExtractError::Cancelled`].
+///
+/// [`ExtractError::Cancelled`]: crate::ExtractError::Cancelled
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Fails the next read once the token is cancelled.
+pub(crate) struct CancellableReader<'a, R> {
+    inner: R,
+    token: &'a CancellationToken,
+}
+
+impl<'a, R: Read> CancellableReader<'a, R> {
+    pub fn new(inner: R, token: &'a CancellationToken) -> Self {
+        Self { inner, token }
+    }
+}
+
+impl<R: Read> Read for CancellableReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.token.is_cancelled() {
+            // Not `Interrupted`, which `io::copy` would retry.
+            return Err(io::Error::other("cancelled"));
+        }
+        self.inner.read(buf)
+    }
+}
@@ -0,0 +1,43 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use rust_decompress::{ExtractError, ZipExtractor};
+use structopt::StructOpt;
+
+use crate::commands;
+use crate::commands::password::PasswordOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct CatOpt {
+    /// The archive to read, or - to read it from standard input
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+
+    /// Names of the entries to print, exactly as stored in the archive
+    #[structopt(required = true)]
+    names: Vec<String>,
+
+    #[structopt(flatten)]
+    password: PasswordOpt,
+}
+
+pub fn run(opt: CatOpt) -> Result<(), ExtractError> {
+    let mut extractor = ZipExtractor::builder(commands::input(&opt.input)).build()?;
+    opt.password.apply(&mut extractor)?;
+    let mut stdout = io::stdout().lock();
+    let result = extractor
+        .cat(&opt.names, &mut stdout)
+        .and_then(|()| Ok(stdout.flush()?));
+    match result {
+        // The reader went away, e.g. `head` got all it wanted.
+        Err(err) if is_broken_pipe(&err) => Ok(()),
+        result => result,
+    }
+}
+
+fn is_broken_pipe(err: &ExtractError) -> bool {
+    match err.root_cause() {
+        ExtractError::IoError(err) => err.kind() == io::ErrorKind::BrokenPipe,
+        _ => false,
+    }
+}
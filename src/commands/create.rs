@@ -0,0 +1,181 @@
+use std::path::{Path, PathBuf};
+
+use rust_decompress::{
+    AddedEntry, CancellationToken, CompressionMethod, EntryFilter, ExtractError, FileKind,
+    ZipCreator,
+};
+use structopt::clap::{self, ErrorKind};
+use structopt::StructOpt;
+use time::OffsetDateTime;
+
+use crate::commands;
+
+#[derive(Debug, StructOpt)]
+pub struct CreateOpt {
+    /// The zip file to write; an existing one is replaced
+    #[structopt(parse(from_os_str))]
+    output: PathBuf,
+
+    /// Files and directories to add, stored under their paths as given. A
+    /// directory takes everything below it along
+    #[structopt(parse(from_os_str), required = true)]
+    sources: Vec<PathBuf>,
+
+    #[structopt(flatten)]
+    source: SourceOpt,
+
+    /// Write the same bytes for the same inputs: sort the entries, record
+    /// one modification time and normalized permissions, and leave out extra
+    /// fields
+    #[structopt(long)]
+    deterministic: bool,
+
+    /// Modification time for every entry with --deterministic, in seconds
+    /// since the Unix epoch; defaults to 1980-01-01
+    #[structopt(long, env = "SOURCE_DATE_EPOCH")]
+    source_date_epoch: Option<String>,
+}
+
+/// How files are picked and compressed when adding them to an archive.
+#[derive(Debug, StructOpt)]
+pub struct SourceOpt {
+    /// How to compress the files
+    #[structopt(short, long, default_value = "deflate", possible_values = CompressionMethod::VARIANTS)]
+    pub method: CompressionMethod,
+
+    /// Compression level: 0-9 for deflate, 1-9 for bzip2, up to 22 for zstd
+    #[structopt(short, long, allow_hyphen_values = true)]
+    pub level: Option<i32>,
+
+    /// Add only files whose names in the archive match this glob pattern;
+    /// can be repeated
+    #[structopt(short, long, number_of_values = 1)]
+    include: Vec<String>,
+
+    /// Leave out files whose names in the archive match this glob pattern;
+    /// can be repeated
+    #[structopt(short = "x", long, number_of_values = 1)]
+    exclude: Vec<String>,
+
+    /// Match the patterns regardless of case
+    #[structopt(short = "C", long)]
+    ignore_case: bool,
+
+    /// Show a progress bar
+    #[structopt(short, long)]
+    pub progress: bool,
+}
+
+impl SourceOpt {
+    pub fn filter(&self) -> Result<EntryFilter, ExtractError> {
+        let mut filter = EntryFilter::builder().case_insensitive(self.ignore_case);
+        for pattern in &self.include {
+            filter = filter.include(pattern.as_str());
+        }
+        for pattern in &self.exclude {
+            filter = filter.exclude(pattern.as_str());
+        }
+        filter.build()
+    }
+}
+
+/// The time to give every entry. It is only read with --deterministic, so
+/// that a SOURCE_DATE_EPOCH set in the environment for other tools does not
+/// fail an ordinary run.
+fn timestamp(opt: &CreateOpt) -> Result<Option<OffsetDateTime>, clap::Error> {
+    let s = match &opt.source_date_epoch {
+        Some(s) if opt.deterministic => s,
+        _ => return Ok(None),
+    };
+    s.trim()
+        .parse::<i64>()
+        .ok()
+        .and_then(|seconds| OffsetDateTime::from_unix_timestamp(seconds).ok())
+        .map(Some)
+        .ok_or_else(|| {
+            clap::Error::with_description(
+                &format!("invalid timestamp for --source-date-epoch: {}", s),
+                ErrorKind::InvalidValue,
+            )
+        })
+}
+
+pub fn run(opt: CreateOpt) -> Result<(), ExtractError> {
+    let timestamp = timestamp(&opt).unwrap_or_else(|err| crate::usage_error(err));
+    let cancel = CancellationToken::new();
+    let mut creator = ZipCreator::builder(&opt.output)
+        .method(opt.source.method)
+        .level(opt.source.level)
+        .filter(opt.source.filter()?)
+        .deterministic(opt.deterministic)
+        .progress(opt.source.progress)
+        .cancel_token(cancel.clone());
+    if let Some(timestamp) = timestamp {
+        creator = creator.timestamp(timestamp);
+    }
+    for source in opt.sources {
+        creator = creator.source(source);
+    }
+    let mut creator = creator.build()?;
+    commands::cancel_on_signal(&cancel);
+    let added_entries = creator.create()?;
+    print_summary(&added_entries, &opt.output);
+    Ok(())
+}
+
+fn print_summary(added_entries: &[AddedEntry], output: &Path) {
+    let count = |wanted: fn(&FileKind) -> bool| {
+        added_entries
+            .iter()
+            .filter(|added_entry| wanted(&added_entry.kind))
+            .count()
+    };
+    let mut parts = Vec::new();
+    for (label, n) in [
+        ("files", count(|kind| matches!(kind, FileKind::File { .. }))),
+        ("directories", count(|kind| *kind == FileKind::Directory)),
+        ("symlinks", count(|kind| *kind == FileKind::Symlink)),
+    ] {
+        if n > 0 {
+            parts.push(format!("{} {}", n, label));
+        }
+    }
+    println!(
+        "Added {} entries to {}: {}",
+        added_entries.len(),
+        output.display(),
+        parts.join(", ")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestamp_of(args: &[&str]) -> Result<Option<OffsetDateTime>, clap::Error> {
+        let args = ["create", "out.zip", "file"].iter().chain(args);
+        timestamp(&CreateOpt::from_iter_safe(args).unwrap())
+    }
+
+    #[test]
+    fn source_date_epoch_applies_only_when_deterministic() {
+        let expected = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        assert_eq!(
+            timestamp_of(&["--deterministic", "--source-date-epoch", "1700000000"]).unwrap(),
+            Some(expected)
+        );
+        assert_eq!(
+            timestamp_of(&["--source-date-epoch", "1700000000"]).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn invalid_source_date_epoch_fails_only_when_deterministic() {
+        assert!(timestamp_of(&["--deterministic", "--source-date-epoch", "soon"]).is_err());
+        assert_eq!(
+            timestamp_of(&["--source-date-epoch", "soon"]).unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use std::path::{Path, PathBuf};
+
+use rust_decompress::{CancellationToken, Edits, ExtractError, ZipEditor};
+use structopt::StructOpt;
+
+use crate::commands;
+use crate::commands::create::SourceOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct AddOpt {
+    /// The zip file to modify
+    #[structopt(parse(from_os_str))]
+    archive: PathBuf,
+
+    /// Files and directories to add, stored under their paths as given. A
+    /// directory takes everything below it along
+    #[structopt(parse(from_os_str), required = true)]
+    sources: Vec<PathBuf>,
+
+    #[structopt(flatten)]
+    source: SourceOpt,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct DeleteOpt {
+    /// The zip file to modify
+    #[structopt(parse(from_os_str))]
+    archive: PathBuf,
+
+    /// Glob patterns of the entries to delete; each must match at least one.
+    /// Deleting a directory deletes everything below it
+    #[structopt(required = true)]
+    patterns: Vec<String>,
+
+    /// Match the patterns regardless of case
+    #[structopt(short = "C", long)]
+    ignore_case: bool,
+}
+
+/// Adds files to an archive, replacing entries of the same name only if
+/// `replace` is set.
+pub fn run_add(opt: AddOpt, replace: bool) -> Result<(), ExtractError> {
+    let cancel = CancellationToken::new();
+    let mut editor = ZipEditor::builder(&opt.archive)
+        .replace(replace)
+        .method(opt.source.method)
+        .level(opt.source.level)
+        .filter(opt.source.filter()?)
+        .progress(opt.source.progress)
+        .cancel_token(cancel.clone());
+    for source in opt.sources {
+        editor = editor.source(source);
+    }
+    let mut editor = editor.build()?;
+    commands::cancel_on_signal(&cancel);
+    let edits = editor.apply()?;
+    print_summary(&edits, &opt.archive);
+    Ok(())
+}
+
+pub fn run_delete(opt: DeleteOpt) -> Result<(), ExtractError> {
+    let cancel = CancellationToken::new();
+    let mut editor = ZipEditor::builder(&opt.archive)
+        .case_insensitive(opt.ignore_case)
+        .cancel_token(cancel.clone());
+    for pattern in &opt.patterns {
+        editor = editor.delete(pattern.as_str());
+    }
+    let mut editor = editor.build()?;
+    commands::cancel_on_signal(&cancel);
+    let edits = editor.apply()?;
+    for name in &edits.deleted {
+        println!("  deleting: {}", name);
+    }
+    print_summary(&edits, &opt.archive);
+    Ok(())
+}
+
+fn print_summary(edits: &Edits, archive: &Path) {
+    let mut parts = Vec::new();
+    for (label, n) in [
+        ("added", edits.added.len()),
+        ("replaced", edits.replaced.len()),
+        ("deleted", edits.deleted.len()),
+    ] {
+        if n > 0 {
+            parts.push(format!("{} {}", n, label));
+        }
+    }
+    parts.push(format!("{} kept", edits.kept));
+    println!("Updated {}: {}", archive.display(), parts.join(", "));
+}
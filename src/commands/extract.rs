@@ -0,0 +1,344 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use rust_decompress::{
+    CancellationToken, EntryFilter, ExtractError, ExtractedFile, Limits, Outcome, OverwritePolicy,
+    SecurityMode, ZipExtractor,
+};
+use structopt::clap::{self, ErrorKind};
+use structopt::StructOpt;
+
+use crate::commands;
+use crate::commands::password::PasswordOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct ExtractOpt {
+    /// The zip or tar file to extract, or - to read it from standard input
+    #[structopt(parse(from_os_str))]
+    input: Option<PathBuf>,
+
+    /// The directory to extract the files to, unless given with -d. A glob
+    /// pattern is refused here rather than taken for a directory
+    #[structopt(parse(from_os_str))]
+    output_dir: Option<PathBuf>,
+
+    /// Extract only entries matching these glob patterns; they follow the
+    /// output directory, or the input with -d. ** matches any number of
+    /// directories
+    patterns: Vec<String>,
+
+    /// The directory to extract the files to; every argument after the input
+    /// is then a pattern
+    #[structopt(
+        short = "d",
+        long = "output-dir",
+        value_name = "dir",
+        parse(from_os_str)
+    )]
+    directory: Option<PathBuf>,
+
+    /// Extract only entries matching this glob pattern; can be repeated
+    #[structopt(short, long, number_of_values = 1)]
+    include: Vec<String>,
+
+    /// Leave out entries matching this glob pattern; can be repeated
+    #[structopt(short = "x", long, number_of_values = 1)]
+    exclude: Vec<String>,
+
+    /// Match the patterns regardless of case
+    #[structopt(short = "C", long)]
+    ignore_case: bool,
+
+    /// Show a progress bar
+    #[structopt(short, long)]
+    progress: bool,
+
+    #[structopt(flatten)]
+    password: PasswordOpt,
+
+    /// What to do with files that already exist
+    #[structopt(long, default_value = "overwrite", possible_values = OverwritePolicy::VARIANTS)]
+    overwrite: OverwritePolicy,
+
+    /// Refuse the whole archive if any entry would be written outside the
+    /// output directory, instead of skipping those entries
+    #[structopt(long)]
+    strict: bool,
+
+    /// Number of entries to extract in parallel; only zip archives are read
+    /// with more than one thread
+    #[structopt(short, long, default_value = "1")]
+    jobs: usize,
+
+    /// Go on with the other entries when one fails, then list the failures
+    #[structopt(short, long)]
+    keep_going: bool,
+
+    /// Extract into a hidden staging directory next to the output directory
+    /// and move it into place only once everything is written. The output
+    /// directory must not exist yet or be empty
+    #[structopt(long)]
+    atomic: bool,
+
+    /// Skip the entries an interrupted extraction into the same output
+    /// directory already wrote, as recorded in its journal
+    #[structopt(long, conflicts_with = "atomic")]
+    resume: bool,
+
+    /// Do not apply the Unix permissions recorded in the archive
+    #[structopt(long)]
+    no_permissions: bool,
+
+    /// Do not apply the modification times recorded in the archive
+    #[structopt(long)]
+    no_mtime: bool,
+
+    /// Refuse archives with more entries than this
+    #[structopt(long)]
+    max_entries: Option<u64>,
+
+    /// Refuse archives larger than this when uncompressed, e.g. 10G
+    #[structopt(long, parse(try_from_str = parse_size))]
+    max_total_size: Option<u64>,
+
+    /// Refuse entries larger than this when uncompressed, e.g. 512M
+    #[structopt(long, parse(try_from_str = parse_size))]
+    max_entry_size: Option<u64>,
+
+    /// Refuse entries that decompress to more than this many times their
+    /// compressed size
+    #[structopt(long)]
+    max_ratio: Option<u64>,
+}
+
+/// Parses a byte count with an optional binary K, M, G or T suffix.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (digits, shift) = match s.to_ascii_uppercase().chars().last() {
+        Some('K') => (&s[..s.len() - 1], 10),
+        Some('M') => (&s[..s.len() - 1], 20),
+        Some('G') => (&s[..s.len() - 1], 30),
+        Some('T') => (&s[..s.len() - 1], 40),
+        _ => (s, 0),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| format!("invalid size: {}", s))
+}
+
+/// Names the output directory after the archive, without its extensions.
+/// An archive read from standard input is extracted into the current
+/// directory.
+fn default_output_dir(input: &Path) -> PathBuf {
+    if input == Path::new("-") {
+        return PathBuf::from(".");
+    }
+    let mut stem = PathBuf::from(input.file_stem().unwrap());
+    if stem.extension() == Some(OsStr::new("tar")) {
+        stem = PathBuf::from(stem.file_stem().unwrap());
+    }
+    PathBuf::from(".").join(stem)
+}
+
+/// Tells the output directory from the patterns among the arguments after
+/// the input. Without -d the first of them is the output directory, unless
+/// it looks like a glob pattern, which would otherwise silently become the
+/// destination.
+fn output_dir_and_patterns(
+    input: &Path,
+    directory: Option<PathBuf>,
+    output_dir: Option<PathBuf>,
+    mut patterns: Vec<String>,
+) -> Result<(PathBuf, Vec<String>), clap::Error> {
+    match (directory, output_dir) {
+        (Some(directory), Some(first)) => {
+            let first = first.into_os_string().into_string().map_err(|first| {
+                clap::Error::with_description(
+                    &format!("{} is not valid Unicode", first.to_string_lossy()),
+                    ErrorKind::InvalidUtf8,
+                )
+            })?;
+            patterns.insert(0, first);
+            Ok((directory, patterns))
+        }
+        (Some(directory), None) => Ok((directory, patterns)),
+        (None, Some(output_dir)) => {
+            let looks_like_pattern = output_dir
+                .to_str()
+                .is_some_and(|name| name.contains(['*', '?', '[', '{']));
+            if looks_like_pattern {
+                return Err(clap::Error::with_description(
+                    &format!(
+                        "{} looks like a pattern, not an output directory; \
+                         give the output directory with -d",
+                        output_dir.display()
+                    ),
+                    ErrorKind::InvalidValue,
+                ));
+            }
+            Ok((output_dir, patterns))
+        }
+        (None, None) => Ok((default_output_dir(input), patterns)),
+    }
+}
+
+pub fn run(opt: ExtractOpt) -> Result<bool, ExtractError> {
+    // Optional only so that subcommands can be used without it.
+    let input = match opt.input {
+        Some(input) => input,
+        None => crate::usage_error(clap::Error::with_description(
+            "The following required arguments were not provided:\n    <input>",
+            ErrorKind::MissingRequiredArgument,
+        )),
+    };
+    let (output_dir, patterns) =
+        output_dir_and_patterns(&input, opt.directory, opt.output_dir, opt.patterns)
+            .unwrap_or_else(|err| crate::usage_error(err));
+    let mut filter = EntryFilter::builder().case_insensitive(opt.ignore_case);
+    for pattern in patterns.into_iter().chain(opt.include) {
+        filter = filter.include(pattern);
+    }
+    for pattern in opt.exclude {
+        filter = filter.exclude(pattern);
+    }
+    let cancel = CancellationToken::new();
+    let mut extractor = ZipExtractor::builder(commands::input(&input))
+        .output_dir(output_dir)
+        .progress(opt.progress)
+        .overwrite(opt.overwrite)
+        .security(if opt.strict {
+            SecurityMode::Strict
+        } else {
+            SecurityMode::Lenient
+        })
+        .preserve_permissions(!opt.no_permissions)
+        .preserve_mtime(!opt.no_mtime)
+        .jobs(opt.jobs)
+        .keep_going(opt.keep_going)
+        .atomic(opt.atomic)
+        .resume(opt.resume)
+        .filter(filter.build()?)
+        .cancel_token(cancel.clone())
+        .limits(Limits {
+            max_entries: opt.max_entries,
+            max_total_size: opt.max_total_size,
+            max_entry_size: opt.max_entry_size,
+            max_ratio: opt.max_ratio,
+        })
+        .build()?;
+    opt.password.apply(&mut extractor)?;
+    // After any password prompt, so that Ctrl-C still aborts it.
+    commands::cancel_on_signal(&cancel);
+    let extracted_files = extractor.extract()?;
+    for finding in extractor.security_findings() {
+        eprintln!("warning: skipped {}", finding);
+    }
+    print_summary(&extracted_files, extractor.security_findings().len());
+    Ok(print_failures(&extracted_files) == 0)
+}
+
+/// Lists the entries that failed under --keep-going and returns how many.
+fn print_failures(extracted_files: &[ExtractedFile]) -> usize {
+    let failures = extracted_files
+        .iter()
+        .filter_map(|extracted_file| Some((&extracted_file.name, extracted_file.error.as_ref()?)))
+        .collect::<Vec<_>>();
+    if failures.is_empty() {
+        return 0;
+    }
+    let header = "Failed entry";
+    let width = failures
+        .iter()
+        .map(|(name, _)| name.chars().count())
+        .chain([header.len()])
+        .max()
+        .unwrap_or_default();
+    println!("{:<width$}  Error", header, width = width);
+    println!("{:-<width$}  -----", "", width = width);
+    for (name, err) in &failures {
+        println!("{:<width$}  {}", name, err.root_cause(), width = width);
+    }
+    failures.len()
+}
+
+fn print_summary(extracted_files: &[ExtractedFile], unsafe_entries: usize) {
+    let count = |wanted: fn(&Outcome) -> bool| {
+        extracted_files
+            .iter()
+            .filter(|extracted_file| wanted(&extracted_file.outcome))
+            .count()
+    };
+    let mut parts = vec![format!(
+        "{} created",
+        count(|outcome| *outcome == Outcome::Created)
+    )];
+    for (label, n) in [
+        (
+            "overwritten",
+            count(|outcome| *outcome == Outcome::Overwritten),
+        ),
+        ("skipped", count(|outcome| *outcome == Outcome::Skipped)),
+        ("resumed", count(|outcome| *outcome == Outcome::Resumed)),
+        ("failed", count(|outcome| *outcome == Outcome::Failed)),
+        (
+            "renamed",
+            count(|outcome| matches!(outcome, Outcome::Renamed(_))),
+        ),
+    ] {
+        if n > 0 {
+            parts.push(format!("{} {}", n, label));
+        }
+    }
+    if unsafe_entries > 0 {
+        parts.push(format!("{} unsafe rejected", unsafe_entries));
+    }
+    println!(
+        "Extracted {} entries: {}",
+        extracted_files.len(),
+        parts.join(", ")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(args: &[&str]) -> Result<(PathBuf, Vec<String>), clap::Error> {
+        let opt = ExtractOpt::from_iter_safe([&["unzip"], args].concat()).unwrap();
+        output_dir_and_patterns(
+            opt.input.as_deref().unwrap(),
+            opt.directory,
+            opt.output_dir,
+            opt.patterns,
+        )
+    }
+
+    #[test]
+    fn argument_after_the_input_is_the_output_dir() {
+        let (output_dir, patterns) = split(&["c.zip", "out", "a/**"]).unwrap();
+        assert_eq!(output_dir, Path::new("out"));
+        assert_eq!(patterns, ["a/**"]);
+    }
+
+    #[test]
+    fn pattern_is_not_taken_for_the_output_dir() {
+        let err = split(&["c.zip", "a.*"]).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidValue);
+    }
+
+    #[test]
+    fn every_argument_is_a_pattern_with_output_dir_option() {
+        let (output_dir, patterns) = split(&["c.zip", "-d", "out", "a.*", "b/**"]).unwrap();
+        assert_eq!(output_dir, Path::new("out"));
+        assert_eq!(patterns, ["a.*", "b/**"]);
+    }
+
+    #[test]
+    fn output_dir_defaults_to_the_archive_name() {
+        let (output_dir, patterns) = split(&["dir/c.tar.gz"]).unwrap();
+        assert_eq!(output_dir, Path::new("./c"));
+        assert!(patterns.is_empty());
+    }
+}
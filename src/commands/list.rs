@@ -0,0 +1,244 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use rust_decompress::{Entry, ExtractError, FileKind, ZipExtractor};
+use serde::Serialize;
+use structopt::StructOpt;
+use time::OffsetDateTime;
+
+use crate::commands;
+
+#[derive(Debug, StructOpt)]
+pub struct ListOpt {
+    /// The archive to list, or - to read it from standard input
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+
+    /// Print the listing as JSON
+    #[structopt(long)]
+    json: bool,
+}
+
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+    name: &'a str,
+    is_dir: bool,
+    size: u64,
+    compressed_size: Option<u64>,
+    method: &'a str,
+    link_target: Option<String>,
+    modified: Option<String>,
+    crc32: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JsonListing<'a> {
+    entries: Vec<JsonEntry<'a>>,
+    total_entries: usize,
+    total_size: u64,
+    total_compressed_size: Option<u64>,
+}
+
+pub fn run(opt: ListOpt) -> Result<(), ExtractError> {
+    let mut extractor = ZipExtractor::builder(commands::input(&opt.input)).build()?;
+    let entries = extractor.entries()?;
+    let mut out = io::stdout().lock();
+    let result = if opt.json {
+        print_json(&mut out, &entries)
+    } else {
+        print_table(&mut out, &entries)
+    };
+    match result {
+        // The listing was piped into something like `head` that stopped reading.
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+/// The sizes are as declared, which can add up to more than fits; the total
+/// then stops at the largest value.
+fn total_size(entries: &[Entry]) -> u64 {
+    entries
+        .iter()
+        .fold(0, |total, entry| total.saturating_add(entry.size))
+}
+
+/// Tar entries have no compressed size of their own, so there is no total.
+fn total_compressed_size(entries: &[Entry]) -> Option<u64> {
+    entries.iter().try_fold(0u64, |total, entry| {
+        Some(total.saturating_add(entry.compressed_size?))
+    })
+}
+
+fn print_table(out: &mut impl Write, entries: &[Entry]) -> io::Result<()> {
+    // Wide enough for methods named by their id, such as "Unsupported(97)".
+    let method_width = entries
+        .iter()
+        .map(|entry| entry.method.len())
+        .max()
+        .unwrap_or(0)
+        .max(10);
+    writeln!(
+        out,
+        "{:>12}  {:>12}  {:<method_width$}  {:<16}  {:<8}  Name",
+        "Length", "Compressed", "Method", "Modified", "CRC-32"
+    )?;
+    writeln!(
+        out,
+        "{:->12}  {:->12}  {:-<method_width$}  {:-<16}  {:-<8}  ----",
+        "", "", "", "", ""
+    )?;
+    for entry in entries {
+        writeln!(
+            out,
+            "{:>12}  {:>12}  {:<method_width$}  {:<16}  {:<8}  {}",
+            entry.size,
+            optional(entry.compressed_size),
+            entry.method,
+            entry.modified.map(format_time).unwrap_or_default(),
+            entry
+                .crc32
+                .map(|crc| format!("{:08x}", crc))
+                .unwrap_or_default(),
+            display_name(entry)
+        )?;
+    }
+    // Method, time and CRC-32 have no totals.
+    let blank = method_width + 30;
+    writeln!(out, "{:->12}  {:->12}  {:blank$}----", "", "", "")?;
+    writeln!(
+        out,
+        "{:>12}  {:>12}  {:blank$}{} entries",
+        total_size(entries),
+        optional(total_compressed_size(entries)),
+        "",
+        entries.len()
+    )
+}
+
+fn print_json(out: &mut impl Write, entries: &[Entry]) -> io::Result<()> {
+    let listing = JsonListing {
+        entries: entries
+            .iter()
+            .map(|entry| JsonEntry {
+                name: &entry.name,
+                is_dir: entry.kind == FileKind::Directory,
+                size: entry.size,
+                compressed_size: entry.compressed_size,
+                method: &entry.method,
+                link_target: entry
+                    .link_target
+                    .as_ref()
+                    .map(|target| target.display().to_string()),
+                modified: entry.modified.map(format_rfc3339),
+                crc32: entry.crc32.map(|crc| format!("{:08x}", crc)),
+            })
+            .collect(),
+        total_entries: entries.len(),
+        total_size: total_size(entries),
+        total_compressed_size: total_compressed_size(entries),
+    };
+    serde_json::to_writer_pretty(&mut *out, &listing)?;
+    writeln!(out)
+}
+
+/// Shows links the way `ls -l` does.
+fn display_name(entry: &Entry) -> String {
+    match &entry.link_target {
+        Some(target) => format!("{} -> {}", entry.name, target.display()),
+        None => entry.name.clone(),
+    }
+}
+
+fn optional(value: Option<u64>) -> String {
+    value
+        .map(|value| value.to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn format_time(time: OffsetDateTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        time.year(),
+        u8::from(time.month()),
+        time.day(),
+        time.hour(),
+        time.minute()
+    )
+}
+
+fn format_rfc3339(time: OffsetDateTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        time.year(),
+        u8::from(time.month()),
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, size: u64, compressed_size: Option<u64>) -> Entry {
+        Entry {
+            index: 0,
+            name: name.to_string(),
+            enclosed_name: Some(PathBuf::from(name)),
+            kind: FileKind::File { size },
+            link_target: None,
+            size,
+            compressed_size,
+            method: "Deflated".to_string(),
+            modified: None,
+            unix_mode: None,
+            crc32: None,
+            encryption: None,
+        }
+    }
+
+    #[test]
+    fn totals_saturate_on_sizes_too_large_to_add() {
+        let entries = [
+            entry("a", 1 << 63, Some(1 << 63)),
+            entry("b", 1 << 63, Some(1 << 63)),
+        ];
+        assert_eq!(total_size(&entries), u64::MAX);
+        assert_eq!(total_compressed_size(&entries), Some(u64::MAX));
+        let mut table = Vec::new();
+        print_table(&mut table, &entries).unwrap();
+        let mut json = Vec::new();
+        print_json(&mut json, &entries).unwrap();
+        let listing: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(listing["total_size"], u64::MAX);
+    }
+
+    #[test]
+    fn tar_entries_have_no_compressed_total() {
+        let entries = [entry("a", 1, Some(1)), entry("b", 2, None)];
+        assert_eq!(total_size(&entries), 3);
+        assert_eq!(total_compressed_size(&entries), None);
+    }
+
+    #[test]
+    fn method_column_fits_the_longest_name() {
+        let mut unsupported = entry("b", 2, Some(2));
+        unsupported.method = "Unsupported(97)".to_string();
+        let entries = [entry("a", 1, Some(1)), unsupported];
+        let mut table = Vec::new();
+        print_table(&mut table, &entries).unwrap();
+        let table = String::from_utf8(table).unwrap();
+        let name_columns = table
+            .lines()
+            .map(|line| line.rfind(|c: char| c.is_whitespace()).unwrap())
+            .collect::<Vec<_>>();
+        assert!(name_columns[..4].iter().all(|&at| at == name_columns[0]));
+        assert_eq!(
+            table.lines().nth(3).unwrap().find("Unsupported(97)"),
+            Some(28)
+        );
+    }
+}
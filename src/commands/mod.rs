@@ -0,0 +1,39 @@
+//! Subcommands of the command line tool besides plain extraction.
+
+pub mod cat;
+pub mod create;
+pub mod edit;
+pub mod extract;
+pub mod list;
+pub mod password;
+pub mod test;
+
+use std::io;
+use std::path::Path;
+use std::process;
+
+use rust_decompress::{CancellationToken, Input};
+
+/// Reads the archive from standard input when the path is `-`.
+pub fn input(path: &Path) -> Input {
+    if path == Path::new("-") {
+        Input::Stream(Box::new(io::stdin()))
+    } else {
+        Input::from(path)
+    }
+}
+
+/// Cancels `token` on SIGINT or SIGTERM so that the running command stops
+/// cleanly. A second signal exits at once.
+pub fn cancel_on_signal(token: &CancellationToken) {
+    let token = token.clone();
+    let installed = ctrlc::set_handler(move || {
+        if token.is_cancelled() {
+            process::exit(crate::EXIT_CANCELLED);
+        }
+        token.cancel();
+    });
+    if let Err(err) = installed {
+        eprintln!("warning: cannot handle interrupts: {}", err);
+    }
+}
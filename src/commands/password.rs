@@ -0,0 +1,62 @@
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+
+use rust_decompress::{ExtractError, Format, ZipExtractor};
+use structopt::StructOpt;
+
+/// Environment variable read when no password option is given.
+const PASSWORD_ENV: &str = "RUST_DECOMPRESS_PASSWORD";
+
+#[derive(Debug, StructOpt)]
+pub struct PasswordOpt {
+    /// Password for encrypted entries. Falls back to --password-file, then to
+    /// the RUST_DECOMPRESS_PASSWORD environment variable, then to a prompt
+    #[structopt(long)]
+    password: Option<String>,
+
+    /// Read the password from the first line of this file
+    #[structopt(long, parse(from_os_str))]
+    password_file: Option<PathBuf>,
+}
+
+impl PasswordOpt {
+    /// Hands the password to the extractor, prompting on the terminal if the
+    /// archive has encrypted entries and no other source provides one.
+    pub fn apply(&self, extractor: &mut ZipExtractor) -> Result<(), ExtractError> {
+        let password = match self.given()? {
+            Some(password) => Some(password),
+            None if needs_password(extractor)? && io::stdin().is_terminal() => {
+                Some(rpassword::prompt_password("Password: ")?)
+            }
+            None => None,
+        };
+        extractor.set_password(password.map(String::into_bytes));
+        Ok(())
+    }
+
+    fn given(&self) -> Result<Option<String>, ExtractError> {
+        if let Some(password) = &self.password {
+            return Ok(Some(password.clone()));
+        }
+        if let Some(path) = &self.password_file {
+            let contents = fs::read_to_string(path)?;
+            let line = contents.lines().next().unwrap_or_default();
+            return Ok(Some(line.to_string()));
+        }
+        Ok(env::var(PASSWORD_ENV).ok())
+    }
+}
+
+/// Only zip entries can be encrypted, so tar archives are not scanned. Nor
+/// are streams, which can only be read once and not decrypted anyway.
+fn needs_password(extractor: &mut ZipExtractor) -> Result<bool, ExtractError> {
+    if extractor.format() != Format::Zip || extractor.is_streaming() {
+        return Ok(false);
+    }
+    Ok(extractor
+        .entries()?
+        .iter()
+        .any(|entry| entry.encryption.is_some()))
+}
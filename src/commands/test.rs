@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+
+use rust_decompress::{CancellationToken, ExtractError, ZipExtractor};
+use structopt::StructOpt;
+
+use crate::commands;
+use crate::commands::password::PasswordOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct TestOpt {
+    /// The archive to test, or - to read it from standard input
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+
+    /// Show a progress bar
+    #[structopt(short, long)]
+    progress: bool,
+
+    #[structopt(flatten)]
+    password: PasswordOpt,
+}
+
+/// Returns whether every entry passed.
+pub fn run(opt: TestOpt) -> Result<bool, ExtractError> {
+    let cancel = CancellationToken::new();
+    let mut extractor = ZipExtractor::builder(commands::input(&opt.input))
+        .progress(opt.progress)
+        .cancel_token(cancel.clone())
+        .build()?;
+    opt.password.apply(&mut extractor)?;
+    commands::cancel_on_signal(&cancel);
+    let tested_entries = extractor.test()?;
+
+    let mut failed = 0;
+    for tested_entry in &tested_entries {
+        match &tested_entry.error {
+            None => println!("    testing: {:<50} OK", tested_entry.name),
+            Some(err) => {
+                failed += 1;
+                println!("    testing: {:<50} FAILED ({})", tested_entry.name, err);
+            }
+        }
+    }
+    if failed == 0 {
+        println!("No errors detected in {}.", opt.input.display());
+    } else {
+        println!(
+            "{} of {} entries failed in {}.",
+            failed,
+            tested_entries.len(),
+            opt.input.display()
+        );
+    }
+    Ok(failed == 0)
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use crate::error::ExtractError;
+
+/// How the files of a created zip archive are compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompressionMethod {
+    /// Store the data as is.
+    Stored,
+    /// Deflate, which every zip reader understands.
+    #[default]
+    Deflate,
+    Bzip2,
+    /// Zstandard, which compresses well and fast but which older readers
+    /// cannot decompress.
+    Zstd,
+}
+
+impl FromStr for CompressionMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "store" => Ok(CompressionMethod::Stored),
+            "deflate" => Ok(CompressionMethod::Deflate),
+            "bzip2" => Ok(CompressionMethod::Bzip2),
+            "zstd" => Ok(CompressionMethod::Zstd),
+            _ => Err(format!("unknown compression method: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for CompressionMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CompressionMethod::Stored => "store",
+            CompressionMethod::Deflate => "deflate",
+            CompressionMethod::Bzip2 => "bzip2",
+            CompressionMethod::Zstd => "zstd",
+        })
+    }
+}
+
+impl CompressionMethod {
+    pub const VARIANTS: &'static [&'static str] = &["store", "deflate", "bzip2", "zstd"];
+
+    /// The compression levels the method accepts, or `None` if it takes none.
+    pub fn levels(self) -> Option<RangeInclusive<i32>> {
+        match self {
+            CompressionMethod::Stored => None,
+            CompressionMethod::Deflate => Some(
+                flate2::Compression::none().level() as i32
+                    ..=flate2::Compression::best().level() as i32,
+            ),
+            // bzip2 has no level that stores the data as is.
+            CompressionMethod::Bzip2 => Some(
+                bzip2::Compression::fast().level() as i32
+                    ..=bzip2::Compression::best().level() as i32,
+            ),
+            CompressionMethod::Zstd => Some(zstd::compression_level_range()),
+        }
+    }
+
+    /// Fails with [`ExtractError::InvalidLevel`] unless the method accepts
+    /// `level`.
+    pub(crate) fn check_level(self, level: Option<i32>) -> Result<(), ExtractError> {
+        match level {
+            Some(level) if !self.levels().is_some_and(|levels| levels.contains(&level)) => {
+                Err(ExtractError::InvalidLevel {
+                    method: self,
+                    level,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn to_zip(self) -> zip::CompressionMethod {
+        match self {
+            CompressionMethod::Stored => zip::CompressionMethod::Stored,
+            CompressionMethod::Deflate => zip::CompressionMethod::Deflated,
+            CompressionMethod::Bzip2 => zip::CompressionMethod::Bzip2,
+            CompressionMethod::Zstd => zip::CompressionMethod::Zstd,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bzip2_levels_start_at_one() {
+        assert_eq!(CompressionMethod::Bzip2.levels(), Some(1..=9));
+        assert!(CompressionMethod::Bzip2.check_level(Some(0)).is_err());
+        assert!(CompressionMethod::Deflate.check_level(Some(0)).is_ok());
+    }
+}
@@ -0,0 +1,39 @@
+use std::io::{self, Read};
+
+/// Computes the CRC-32 and length of everything read through it.
+pub(crate) struct CrcReader<R> {
+    inner: R,
+    hasher: crc32fast::Hasher,
+    len: u64,
+}
+
+impl<R: Read> CrcReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: crc32fast::Hasher::new(),
+            len: 0,
+        }
+    }
+
+    pub fn crc32(&self) -> u32 {
+        self.hasher.clone().finalize()
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+}
+
+impl<R: Read> Read for CrcReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.hasher.update(&buf[..count]);
+        self.len += count as u64;
+        Ok(count)
+    }
+}
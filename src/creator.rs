@@ -0,0 +1,458 @@
+use std::collections::HashSet;
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+
+use indicatif::ProgressBar;
+use time::OffsetDateTime;
+use walkdir::WalkDir;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::attributes;
+use crate::cancel::{CancellableReader, CancellationToken};
+use crate::compression::CompressionMethod;
+use crate::error::ExtractError;
+use crate::extractor::FileKind;
+use crate::filter::EntryFilter;
+use crate::progress::{self, ProgressReader};
+
+const EXTENDED_TIMESTAMP_EXTRA_FIELD: u16 = 0x5455;
+/// Entries at least this large need the zip64 format.
+const ZIP64_SIZE: u64 = 0xFFFF_FFFF;
+/// 1980-01-01, the earliest time a zip entry can hold.
+const ZIP_EPOCH: i64 = 315_532_800;
+
+/// A file, directory or symlink written to a new archive.
+#[derive(Debug, Clone)]
+pub struct AddedEntry {
+    /// The name it is stored under.
+    pub name: String,
+    /// Where it was read from.
+    pub path: PathBuf,
+    pub kind: FileKind,
+    /// What a symlink points to.
+    pub link_target: Option<PathBuf>,
+    pub modified: Option<OffsetDateTime>,
+    pub unix_mode: Option<u32>,
+}
+
+/// Settings that shape how entries are written.
+#[derive(Debug, Clone, Default)]
+struct Options {
+    method: CompressionMethod,
+    level: Option<i32>,
+    filter: EntryFilter,
+    deterministic: bool,
+    timestamp: Option<OffsetDateTime>,
+    cancel: CancellationToken,
+}
+
+/// Configures a [`ZipCreator`]; created with [`ZipCreator::builder`].
+pub struct ZipCreatorBuilder {
+    output: PathBuf,
+    sources: Vec<PathBuf>,
+    progress: bool,
+    options: Options,
+}
+
+impl ZipCreatorBuilder {
+    /// Adds a file, or a directory with everything below it. Entries are
+    /// named after their paths as given, resolving `..` and dropping a
+    /// leading `/`, so adding `src/lib` stores `src/lib/` and its contents
+    /// while adding `.` stores what is below the current directory.
+    /// Symlinks inside a directory are stored as symlinks rather than
+    /// followed.
+    pub fn source(mut self, path: impl Into<PathBuf>) -> Self {
+        self.sources.push(path.into());
+        self
+    }
+
+    /// How files are compressed. Defaults to [`CompressionMethod::Deflate`].
+    pub fn method(mut self, method: CompressionMethod) -> Self {
+        self.options.method = method;
+        self
+    }
+
+    /// Compression level within [`CompressionMethod::levels`], or `None` for
+    /// the default of the method.
+    pub fn level(mut self, level: Option<i32>) -> Self {
+        self.options.level = level;
+        self
+    }
+
+    /// Chooses which files are added by their names in the archive.
+    pub fn filter(mut self, filter: EntryFilter) -> Self {
+        self.options.filter = filter;
+        self
+    }
+
+    /// Writes the same bytes for the same file contents and names, wherever
+    /// and whenever the archive is created: entries are sorted by name, all
+    /// carry the [`timestamp`](Self::timestamp), files get mode 644, or 755
+    /// if anyone may execute them, directories 755, and no extra fields are
+    /// written. Off by default.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.options.deterministic = deterministic;
+        self
+    }
+
+    /// Modification time recorded for every entry of a
+    /// [`deterministic`](Self::deterministic) archive, such as the
+    /// `SOURCE_DATE_EPOCH` of a reproducible build. A zip entry holds times
+    /// from 1980 to 2107 with a precision of two seconds; anything else is
+    /// recorded as 1980-01-01, which is also the default. Unlike other
+    /// archives, where it is in local time as zip tools expect, it is
+    /// written in UTC so as not to depend on the time zone.
+    pub fn timestamp(mut self, timestamp: OffsetDateTime) -> Self {
+        self.options.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Show a progress bar on the terminal.
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Token that stops the creation when cancelled, leaving nothing at the
+    /// output path.
+    pub fn cancel_token(mut self, token: CancellationToken) -> Self {
+        self.options.cancel = token;
+        self
+    }
+
+    /// Checks the compression level against the method.
+    pub fn build(self) -> Result<ZipCreator, ExtractError> {
+        self.options.method.check_level(self.options.level)?;
+        Ok(ZipCreator {
+            output: self.output,
+            sources: self.sources,
+            progress_bar: self.progress.then(|| progress::progress_bar(true)),
+            options: self.options,
+        })
+    }
+}
+
+/// Writes a zip archive from files and directories on disk.
+///
+/// ```no_run
+/// use rust_decompress::{CompressionMethod, ZipCreator};
+///
+/// let entries = ZipCreator::builder("site.zip")
+///     .source("public")
+///     .method(CompressionMethod::Zstd)
+///     .build()?
+///     .create()?;
+/// println!("added {} entries", entries.len());
+/// # Ok::<(), rust_decompress::ExtractError>(())
+/// ```
+pub struct ZipCreator {
+    output: PathBuf,
+    sources: Vec<PathBuf>,
+    progress_bar: Option<ProgressBar>,
+    options: Options,
+}
+
+/// A file found under one of the sources, to be written as `name`.
+pub(crate) struct Source {
+    pub name: String,
+    path: PathBuf,
+    pub kind: FileKind,
+    link_target: Option<PathBuf>,
+    metadata: Metadata,
+}
+
+impl ZipCreator {
+    pub fn builder(output: impl Into<PathBuf>) -> ZipCreatorBuilder {
+        ZipCreatorBuilder {
+            output: output.into(),
+            sources: Vec::new(),
+            progress: false,
+            options: Options::default(),
+        }
+    }
+
+    pub fn output(&self) -> &Path {
+        &self.output
+    }
+
+    /// Writes the archive next to the output path and moves it into place
+    /// once complete, replacing any file there. Unless the archive is
+    /// [`deterministic`](ZipCreatorBuilder::deterministic), each entry records
+    /// its Unix permissions and modification time, for files to the second in
+    /// the extended timestamp field. A name that comes up twice is stored once, from the
+    /// first source; sockets and other special files are left out.
+    pub fn create(&mut self) -> Result<Vec<AddedEntry>, ExtractError> {
+        let result = self.create_archive();
+        self.abandon_if_cancelled(&result);
+        result.map_err(|err| err.in_archive(Some(&self.output)))
+    }
+
+    /// Ends the progress bar with "Cancelled" after a cancelled run.
+    pub(crate) fn abandon_if_cancelled<T>(&self, result: &Result<T, ExtractError>) {
+        if let (Some(pb), Err(ExtractError::Cancelled)) = (&self.progress_bar, result) {
+            pb.abandon_with_message("Cancelled");
+        }
+    }
+
+    fn create_archive(&mut self) -> Result<Vec<AddedEntry>, ExtractError> {
+        let temp_path = temp_path(&self.output, "partial")?;
+        let file = File::create(&temp_path)?;
+        // Neither the archive being written nor the one it replaces belongs
+        // in it when it is created inside one of the sources.
+        let mut skipped = vec![file.metadata()?];
+        skipped.extend(fs::metadata(&self.output).ok());
+        let result = self
+            .collect_sources(&skipped)
+            .and_then(|sources| self.write_sources(file, &sources))
+            .and_then(|added_entries| {
+                fs::rename(&temp_path, &self.output)?;
+                Ok(added_entries)
+            });
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
+    }
+
+    /// Walks the sources, in the order given and each depth first.
+    pub(crate) fn collect_sources(
+        &self,
+        skipped: &[Metadata],
+    ) -> Result<Vec<Source>, ExtractError> {
+        let mut names = HashSet::new();
+        let mut sources = Vec::new();
+        for source in &self.sources {
+            for dir_entry in WalkDir::new(source) {
+                let dir_entry = dir_entry.map_err(io::Error::from)?;
+                let path = dir_entry.path();
+                let name = entry_name(path)?;
+                if name.is_empty()
+                    || !self.options.filter.matches_name(&name)
+                    || names.contains(&name)
+                {
+                    continue;
+                }
+                let metadata = dir_entry.metadata().map_err(io::Error::from)?;
+                if skipped.iter().any(|skipped| same_file(skipped, &metadata)) {
+                    continue;
+                }
+                let file_type = metadata.file_type();
+                let (kind, link_target) = if file_type.is_dir() {
+                    (FileKind::Directory, None)
+                } else if file_type.is_symlink() {
+                    (FileKind::Symlink, Some(fs::read_link(path)?))
+                } else if file_type.is_file() {
+                    (
+                        FileKind::File {
+                            size: metadata.len(),
+                        },
+                        None,
+                    )
+                } else {
+                    continue;
+                };
+                names.insert(name.clone());
+                sources.push(Source {
+                    name,
+                    path: path.to_path_buf(),
+                    kind,
+                    link_target,
+                    metadata,
+                });
+            }
+        }
+        if self.options.deterministic {
+            sources.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        Ok(sources)
+    }
+
+    pub(crate) fn write_sources(
+        &self,
+        file: File,
+        sources: &[Source],
+    ) -> Result<Vec<AddedEntry>, ExtractError> {
+        if let Some(pb) = &self.progress_bar {
+            pb.set_length(
+                sources
+                    .iter()
+                    .map(|source| match source.kind {
+                        FileKind::File { size } => size,
+                        _ => 0,
+                    })
+                    .sum(),
+            );
+        }
+        let mut zip = ZipWriter::new(BufWriter::new(file));
+        let mut added_entries = Vec::with_capacity(sources.len());
+        for source in sources {
+            if self.options.cancel.is_cancelled() {
+                return Err(ExtractError::Cancelled);
+            }
+            if let Some(pb) = &self.progress_bar {
+                pb.set_message(source.name.clone());
+            }
+            let added_entry = self
+                .write_source(&mut zip, source)
+                .map_err(|err| err.in_entry(&source.name, None))?;
+            added_entries.push(added_entry);
+        }
+        zip.finish()?
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        if let Some(pb) = &self.progress_bar {
+            pb.finish_with_message(format!("Added {} entries", added_entries.len()));
+        }
+        Ok(added_entries)
+    }
+
+    fn write_source(
+        &self,
+        zip: &mut ZipWriter<BufWriter<File>>,
+        source: &Source,
+    ) -> Result<AddedEntry, ExtractError> {
+        let (modified, unix_mode) = if self.options.deterministic {
+            (
+                Some(
+                    self.options
+                        .timestamp
+                        .filter(|&timestamp| zip::DateTime::try_from(timestamp).is_ok())
+                        .unwrap_or_else(zip_epoch),
+                ),
+                Some(normalized_mode(source.kind, &source.metadata)),
+            )
+        } else {
+            (
+                source.metadata.modified().ok().map(OffsetDateTime::from),
+                attributes::unix_mode(&source.metadata),
+            )
+        };
+        let dos_time = if self.options.deterministic {
+            modified.and_then(|time| time.try_into().ok())
+        } else {
+            modified.and_then(attributes::local_dos_time)
+        };
+        let mut options = FileOptions::default().last_modified_time(dos_time.unwrap_or_default());
+        if let Some(mode) = unix_mode {
+            options = options.unix_permissions(mode);
+        }
+        match (&source.kind, &source.link_target) {
+            (FileKind::Directory, _) => zip.add_directory(source.name.as_str(), options)?,
+            (FileKind::Symlink, Some(target)) => {
+                let target = target.to_str().ok_or_else(|| not_unicode(target))?;
+                zip.add_symlink(source.name.as_str(), target, options)?;
+            }
+            (FileKind::File { size }, _) => {
+                let options = options
+                    .compression_method(self.options.method.to_zip())
+                    .compression_level(self.options.level)
+                    .large_file(*size >= ZIP64_SIZE);
+                let mut file = File::open(&source.path)?;
+                zip.start_file_with_extra_data(source.name.as_str(), options)?;
+                if !self.options.deterministic {
+                    if let Some(field) = modified.and_then(extended_timestamp) {
+                        zip.write_all(&field)?;
+                    }
+                }
+                zip.end_extra_data()?;
+                let reader = CancellableReader::new(&mut file, &self.options.cancel);
+                let mut reader = ProgressReader::new(reader, self.progress_bar.as_ref());
+                if let Err(err) = io::copy(&mut reader, zip) {
+                    return Err(if self.options.cancel.is_cancelled() {
+                        ExtractError::Cancelled
+                    } else {
+                        err.into()
+                    });
+                }
+            }
+            _ => unreachable!("only files, directories and symlinks are collected"),
+        }
+        Ok(AddedEntry {
+            name: source.name.clone(),
+            path: source.path.clone(),
+            kind: source.kind,
+            link_target: source.link_target.clone(),
+            modified,
+            unix_mode,
+        })
+    }
+}
+
+fn zip_epoch() -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp(ZIP_EPOCH).unwrap()
+}
+
+/// The mode recorded in a deterministic archive, which keeps only whether a
+/// file is executable.
+fn normalized_mode(kind: FileKind, metadata: &Metadata) -> u32 {
+    match kind {
+        FileKind::Directory => 0o755,
+        FileKind::Symlink => 0o777,
+        _ if attributes::unix_mode(metadata).is_some_and(|mode| mode & 0o111 != 0) => 0o755,
+        _ => 0o644,
+    }
+}
+
+/// A hidden sibling of `output` to write to before the result is moved into
+/// place, told apart from others by `suffix`.
+pub(crate) fn temp_path(output: &Path, suffix: &str) -> Result<PathBuf, ExtractError> {
+    let name = output.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} does not name a file", output.display()),
+        )
+    })?;
+    let temp_name = format!(".{}.{}-{}", name.to_string_lossy(), suffix, process::id());
+    Ok(output.with_file_name(temp_name))
+}
+
+/// Joins the components of a path with `/`, as zip names are, leaving out
+/// whatever would reach above the archive root.
+fn entry_name(path: &Path) -> Result<String, ExtractError> {
+    let mut names = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => names.push(name.to_str().ok_or_else(|| not_unicode(path))?),
+            Component::ParentDir => {
+                names.pop();
+            }
+            _ => {}
+        }
+    }
+    Ok(names.join("/"))
+}
+
+fn not_unicode(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} is not valid Unicode", path.display()),
+    )
+}
+
+/// The extended timestamp extra field holding `modified`, if it fits in its
+/// 32 bits.
+fn extended_timestamp(modified: OffsetDateTime) -> Option<Vec<u8>> {
+    let mtime = i32::try_from(modified.unix_timestamp()).ok()?;
+    let mut field = Vec::with_capacity(9);
+    field.extend_from_slice(&EXTENDED_TIMESTAMP_EXTRA_FIELD.to_le_bytes());
+    field.extend_from_slice(&5u16.to_le_bytes());
+    // Flags: only the modification time follows.
+    field.push(1);
+    field.extend_from_slice(&mtime.to_le_bytes());
+    Some(field)
+}
+
+#[cfg(unix)]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &Metadata, _b: &Metadata) -> bool {
+    false
+}
@@ -0,0 +1,296 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+use crate::cancel::CancellationToken;
+use crate::compression::CompressionMethod;
+use crate::creator::{self, AddedEntry, ZipCreator, ZipCreatorBuilder};
+use crate::error::ExtractError;
+use crate::extractor::FileKind;
+use crate::filter::EntryFilter;
+use crate::splice::{self, RawEntry, Splicer};
+
+/// What [`ZipEditor::apply`] did to the archive.
+#[derive(Debug, Default)]
+pub struct Edits {
+    /// Entries stored under names the archive did not have yet.
+    pub added: Vec<AddedEntry>,
+    /// Entries written in place of ones with the same name.
+    pub replaced: Vec<AddedEntry>,
+    /// Names of the entries removed.
+    pub deleted: Vec<String>,
+    /// Number of entries copied over as they were.
+    pub kept: usize,
+}
+
+/// Configures a [`ZipEditor`]; created with [`ZipEditor::builder`].
+pub struct ZipEditorBuilder {
+    archive: PathBuf,
+    creator: ZipCreatorBuilder,
+    replace: bool,
+    delete: Vec<String>,
+    case_insensitive: bool,
+    cancel: CancellationToken,
+}
+
+impl ZipEditorBuilder {
+    /// Adds a file, or a directory with everything below it, named as with
+    /// [`ZipCreatorBuilder::source`].
+    pub fn source(mut self, path: impl Into<PathBuf>) -> Self {
+        self.creator = self.creator.source(path);
+        self
+    }
+
+    /// Whether added files replace entries of the same name. Otherwise such
+    /// a file fails the edit with [`ExtractError::EntryExists`], while a
+    /// directory the archive already has is left as it is. Off by default.
+    pub fn replace(mut self, replace: bool) -> Self {
+        self.replace = replace;
+        self
+    }
+
+    /// Removes the entries matching the glob `pattern`, which must match at
+    /// least one; see [`EntryFilter`] for the syntax.
+    pub fn delete(mut self, pattern: impl Into<String>) -> Self {
+        self.delete.push(pattern.into());
+        self
+    }
+
+    /// Match the [`delete`](Self::delete) patterns regardless of case. Off by
+    /// default.
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// How added files are compressed. Defaults to
+    /// [`CompressionMethod::Deflate`].
+    pub fn method(mut self, method: CompressionMethod) -> Self {
+        self.creator = self.creator.method(method);
+        self
+    }
+
+    /// Compression level for added files; see [`ZipCreatorBuilder::level`].
+    pub fn level(mut self, level: Option<i32>) -> Self {
+        self.creator = self.creator.level(level);
+        self
+    }
+
+    /// Chooses which files are added by their names in the archive.
+    pub fn filter(mut self, filter: EntryFilter) -> Self {
+        self.creator = self.creator.filter(filter);
+        self
+    }
+
+    /// Show a progress bar on the terminal while adding files.
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.creator = self.creator.progress(progress);
+        self
+    }
+
+    /// Token that stops the edit when cancelled, leaving the archive as it
+    /// was.
+    pub fn cancel_token(mut self, token: CancellationToken) -> Self {
+        self.creator = self.creator.cancel_token(token.clone());
+        self.cancel = token;
+        self
+    }
+
+    /// Compiles the delete patterns and checks the compression level.
+    pub fn build(self) -> Result<ZipEditor, ExtractError> {
+        let delete = self
+            .delete
+            .into_iter()
+            .map(|pattern| {
+                let filter = EntryFilter::builder()
+                    .include(pattern.as_str())
+                    .case_insensitive(self.case_insensitive)
+                    .build()?;
+                Ok((pattern, filter))
+            })
+            .collect::<Result<Vec<_>, ExtractError>>()?;
+        Ok(ZipEditor {
+            creator: self.creator.build()?,
+            archive: self.archive,
+            replace: self.replace,
+            delete,
+            cancel: self.cancel,
+        })
+    }
+}
+
+/// Adds, replaces and deletes entries of an existing zip archive.
+///
+/// The archive is rewritten next to itself and then moved over the
+/// original, so that it is never left half modified. Entries that are kept
+/// are copied byte for byte, without being decompressed and compressed
+/// again, and keep their place; added entries go at the end.
+///
+/// ```no_run
+/// use rust_decompress::ZipEditor;
+///
+/// let edits = ZipEditor::builder("site.zip")
+///     .source("public/index.html")
+///     .replace(true)
+///     .delete("public/drafts")
+///     .build()?
+///     .apply()?;
+/// println!("replaced {} entries", edits.replaced.len());
+/// # Ok::<(), rust_decompress::ExtractError>(())
+/// ```
+pub struct ZipEditor {
+    archive: PathBuf,
+    creator: ZipCreator,
+    replace: bool,
+    delete: Vec<(String, EntryFilter)>,
+    cancel: CancellationToken,
+}
+
+impl ZipEditor {
+    pub fn builder(archive: impl Into<PathBuf>) -> ZipEditorBuilder {
+        let archive = archive.into();
+        ZipEditorBuilder {
+            creator: ZipCreator::builder(&archive),
+            archive,
+            replace: false,
+            delete: Vec::new(),
+            case_insensitive: false,
+            cancel: CancellationToken::default(),
+        }
+    }
+
+    pub fn archive(&self) -> &Path {
+        &self.archive
+    }
+
+    pub fn apply(&mut self) -> Result<Edits, ExtractError> {
+        let result = self.edit_archive();
+        self.creator.abandon_if_cancelled(&result);
+        result.map_err(|err| err.in_archive(Some(&self.archive)))
+    }
+
+    fn edit_archive(&mut self) -> Result<Edits, ExtractError> {
+        let mut original = File::open(&self.archive)?;
+        let (entries, comment) = splice::read_entries(&mut original)?;
+        let deleted = self.deleted(&entries)?;
+        let temp_path = creator::temp_path(&self.archive, "partial")?;
+        // Added files are compressed into an archive of their own first, to
+        // be spliced in from there.
+        let added_path = creator::temp_path(&self.archive, "added")?;
+        let result = self.rewrite(
+            &mut original,
+            &entries,
+            &deleted,
+            &comment,
+            &temp_path,
+            &added_path,
+        );
+        let _ = fs::remove_file(&added_path);
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
+    }
+
+    /// Marks the entries matching a delete pattern, failing with
+    /// [`ExtractError::EntryNotFound`] for a pattern that matches none.
+    fn deleted(&self, entries: &[RawEntry]) -> Result<Vec<bool>, ExtractError> {
+        let mut deleted = vec![false; entries.len()];
+        for (pattern, filter) in &self.delete {
+            let mut found = false;
+            for (index, entry) in entries.iter().enumerate() {
+                if filter.matches_name(&entry.name) {
+                    deleted[index] = true;
+                    found = true;
+                }
+            }
+            if !found {
+                return Err(ExtractError::EntryNotFound(pattern.clone()));
+            }
+        }
+        Ok(deleted)
+    }
+
+    fn rewrite(
+        &self,
+        original: &mut File,
+        entries: &[RawEntry],
+        deleted: &[bool],
+        comment: &[u8],
+        temp_path: &Path,
+        added_path: &Path,
+    ) -> Result<Edits, ExtractError> {
+        let added_file = File::create(added_path)?;
+        let temp_file = File::create(temp_path)?;
+        let skipped = [
+            original.metadata()?,
+            added_file.metadata()?,
+            temp_file.metadata()?,
+        ];
+
+        let by_name = entries
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| !deleted[index])
+            .map(|(index, entry)| (entry.name.trim_end_matches('/'), index))
+            .collect::<HashMap<_, _>>();
+        let mut sources = Vec::new();
+        let mut replaced = HashSet::new();
+        for source in self.creator.collect_sources(&skipped)? {
+            match by_name.get(source.name.as_str()) {
+                None => {}
+                Some(&index) if self.replace => {
+                    replaced.insert(index);
+                }
+                Some(&index) if source.kind == FileKind::Directory && entries[index].is_dir => {
+                    continue
+                }
+                Some(_) => return Err(ExtractError::EntryExists(source.name)),
+            }
+            sources.push(source);
+        }
+        let added_entries = self.creator.write_sources(added_file, &sources)?;
+        let mut added_file = File::open(added_path)?;
+        let (added, _) = splice::read_entries(&mut added_file)?;
+        let added_by_name = added
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| (entry.name.trim_end_matches('/'), index))
+            .collect::<HashMap<_, _>>();
+
+        let mut edits = Edits::default();
+        let mut spliced = vec![false; added.len()];
+        let mut splicer = Splicer::new(BufWriter::new(temp_file));
+        for (index, entry) in entries.iter().enumerate() {
+            if self.cancel.is_cancelled() {
+                return Err(ExtractError::Cancelled);
+            }
+            if deleted[index] {
+                edits.deleted.push(entry.name.clone());
+            } else if replaced.contains(&index) {
+                let added_index = added_by_name[entry.name.trim_end_matches('/')];
+                splicer.copy(&mut added_file, &added[added_index])?;
+                spliced[added_index] = true;
+                edits.replaced.push(added_entries[added_index].clone());
+            } else {
+                splicer.copy(original, entry)?;
+                edits.kept += 1;
+            }
+        }
+        for (added_index, entry) in added.iter().enumerate() {
+            if !spliced[added_index] {
+                splicer.copy(&mut added_file, entry)?;
+                edits.added.push(added_entries[added_index].clone());
+            }
+        }
+        splicer
+            .finish(comment)?
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+
+        fs::set_permissions(temp_path, original.metadata()?.permissions())?;
+        fs::rename(temp_path, &self.archive)?;
+        Ok(edits)
+    }
+}
@@ -0,0 +1,292 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::compression::CompressionMethod;
+use crate::limits::LimitKind;
+use crate::security::SecurityFinding;
+
+/// Errors of opening, extracting, testing and creating archives. Failures inside an
+/// entry come wrapped in [`ExtractError::InEntry`], and those of an archive
+/// opened by path in [`ExtractError::InArchive`]; [`root_cause`] unwraps them.
+///
+/// [`root_cause`]: ExtractError::root_cause
+#[derive(Debug)]
+pub enum ExtractError {
+    IoError(io::Error),
+    ZipError(zip::result::ZipError),
+    /// The input is not an archive format we can read.
+    UnrecognizedFormat {
+        detected: String,
+    },
+    /// The data of an entry does not match the CRC-32 recorded for it.
+    CrcMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    /// An entry holds a different number of bytes than it declares.
+    SizeMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    /// A file is in the way and the overwrite policy forbids replacing it.
+    AlreadyExists(PathBuf),
+    /// The output directory cannot be created or written to.
+    OutputDir {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// Strict mode found entries that are unsafe to extract.
+    UnsafeEntries(Vec<SecurityFinding>),
+    /// An entry is encrypted and no password was given.
+    PasswordRequired,
+    /// The password does not decrypt an entry.
+    WrongPassword,
+    /// An entry is encrypted with a scheme other than ZipCrypto or WinZip AES.
+    UnsupportedEncryption,
+    /// An entry is compressed with a method we cannot decompress, by its id
+    /// in the zip format. Deflate64, LZMA and XZ are only decoded in entries
+    /// that are not encrypted.
+    UnsupportedMethod {
+        method: u16,
+        encrypted: bool,
+    },
+    /// The archive declares or decompresses to more than a resource limit
+    /// allows.
+    LimitExceeded {
+        kind: LimitKind,
+        limit: u64,
+        actual: u64,
+    },
+    /// No entry is stored under the requested name.
+    EntryNotFound(String),
+    /// An archive already holds an entry under the name of one being added.
+    EntryExists(String),
+    /// The requested entry is a directory, which has no data to read.
+    IsDirectory(String),
+    /// An entry filter pattern is not a valid glob.
+    InvalidPattern {
+        pattern: String,
+        reason: String,
+    },
+    /// The compression method does not accept the requested level.
+    InvalidLevel {
+        method: CompressionMethod,
+        level: i32,
+    },
+    /// The extraction or creation was stopped through its
+    /// [`CancellationToken`].
+    ///
+    /// [`CancellationToken`]: crate::CancellationToken
+    Cancelled,
+    /// Reading or writing an entry failed.
+    InEntry {
+        /// The name as stored in the archive.
+        name: String,
+        /// Where the entry was being written, if anywhere.
+        path: Option<PathBuf>,
+        source: Box<ExtractError>,
+    },
+    /// Opening or reading the archive at `archive` failed.
+    InArchive {
+        archive: PathBuf,
+        source: Box<ExtractError>,
+    },
+}
+
+impl ExtractError {
+    /// The error beneath the archive and entry it happened in.
+    pub fn root_cause(&self) -> &ExtractError {
+        match self {
+            ExtractError::InEntry { source, .. } | ExtractError::InArchive { source, .. } => {
+                source.root_cause()
+            }
+            err => err,
+        }
+    }
+
+    pub(crate) fn in_entry(self, name: &str, path: Option<&Path>) -> Self {
+        match self {
+            err @ (ExtractError::InEntry { .. } | ExtractError::Cancelled) => err,
+            err => ExtractError::InEntry {
+                name: name.to_string(),
+                path: path.map(Path::to_path_buf),
+                source: Box::new(err),
+            },
+        }
+    }
+
+    pub(crate) fn output_dir(path: &Path, err: io::Error) -> Self {
+        ExtractError::OutputDir {
+            path: path.to_path_buf(),
+            source: err,
+        }
+    }
+
+    /// Adds the path of the archive, unless it was not opened by path or the
+    /// error is about where the entries go rather than the archive.
+    pub(crate) fn in_archive(self, archive: Option<&Path>) -> Self {
+        match (self, archive) {
+            (err, _)
+                if matches!(
+                    err.root_cause(),
+                    ExtractError::OutputDir { .. } | ExtractError::AlreadyExists(_)
+                ) =>
+            {
+                err
+            }
+            (err, None) => err,
+            (err, Some(archive)) => ExtractError::InArchive {
+                archive: archive.to_path_buf(),
+                source: Box::new(err),
+            },
+        }
+    }
+}
+
+impl From<io::Error> for ExtractError {
+    fn from(err: io::Error) -> Self {
+        ExtractError::IoError(err)
+    }
+}
+
+impl From<zip::result::ZipError> for ExtractError {
+    fn from(err: zip::result::ZipError) -> Self {
+        ExtractError::ZipError(err)
+    }
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractError::IoError(err) => write!(f, "{}", err),
+            ExtractError::ZipError(err) => write!(f, "{}", err),
+            ExtractError::UnrecognizedFormat { detected } => {
+                write!(f, "unrecognized archive format: found {}", detected)
+            }
+            ExtractError::CrcMismatch { expected, actual } => write!(
+                f,
+                "CRC-32 mismatch: expected {:08x}, got {:08x}",
+                expected, actual
+            ),
+            ExtractError::SizeMismatch { expected, actual } => write!(
+                f,
+                "size mismatch: expected {} bytes, got {}",
+                expected, actual
+            ),
+            ExtractError::AlreadyExists(path) => {
+                write!(f, "{} already exists", path.display())
+            }
+            ExtractError::OutputDir { path, source } => {
+                write!(f, "output directory {}: {}", path.display(), source)
+            }
+            ExtractError::UnsafeEntries(findings) => {
+                write!(f, "refusing to extract {} unsafe entries", findings.len())?;
+                for finding in findings {
+                    write!(f, "\n  {}", finding)?;
+                }
+                Ok(())
+            }
+            ExtractError::PasswordRequired => {
+                write!(f, "entry is encrypted, a password is required")
+            }
+            ExtractError::WrongPassword => write!(f, "wrong password"),
+            ExtractError::UnsupportedEncryption => write!(
+                f,
+                "unsupported encryption; only ZipCrypto and WinZip AES can be decrypted"
+            ),
+            ExtractError::UnsupportedMethod {
+                method,
+                encrypted: false,
+            } => write!(f, "unsupported compression method {}", method),
+            ExtractError::UnsupportedMethod {
+                method,
+                encrypted: true,
+            } => write!(
+                f,
+                "unsupported compression method {} in an encrypted entry",
+                method
+            ),
+            ExtractError::LimitExceeded {
+                kind,
+                limit,
+                actual,
+            } => write!(f, "{} limit exceeded: {} > {}", kind, actual, limit),
+            ExtractError::EntryNotFound(name) => write!(f, "{}: no such entry", name),
+            ExtractError::EntryExists(name) => write!(f, "{}: entry already exists", name),
+            ExtractError::IsDirectory(name) => write!(f, "{}: is a directory", name),
+            ExtractError::InvalidPattern { pattern, reason } => {
+                write!(f, "invalid pattern {:?}: {}", pattern, reason)
+            }
+            ExtractError::InvalidLevel { method, level } => match method.levels() {
+                Some(levels) => write!(
+                    f,
+                    "invalid {} compression level {}: expected {} to {}",
+                    method,
+                    level,
+                    levels.start(),
+                    levels.end()
+                ),
+                None => write!(f, "{} takes no compression level", method),
+            },
+            ExtractError::Cancelled => write!(f, "cancelled"),
+            ExtractError::InEntry {
+                name,
+                path: Some(path),
+                source,
+            } => write!(f, "{} (to {}): {}", name, path.display(), source),
+            ExtractError::InEntry {
+                name,
+                path: None,
+                source,
+            } => write!(f, "{}: {}", name, source),
+            ExtractError::InArchive { archive, source } => {
+                write!(f, "{}: {}", archive.display(), source)
+            }
+        }
+    }
+}
+
+impl Error for ExtractError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ExtractError::IoError(err) | ExtractError::OutputDir { source: err, .. } => Some(err),
+            ExtractError::ZipError(err) => Some(err),
+            ExtractError::InEntry { source, .. } | ExtractError::InArchive { source, .. } => {
+                Some(source.as_ref())
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archive_errors_name_the_archive() {
+        let err = ExtractError::CrcMismatch {
+            expected: 1,
+            actual: 2,
+        }
+        .in_entry("a", None)
+        .in_archive(Some(Path::new("archive.zip")));
+        assert_eq!(
+            err.to_string(),
+            "archive.zip: a: CRC-32 mismatch: expected 00000001, got 00000002"
+        );
+    }
+
+    #[test]
+    fn output_errors_do_not_name_the_archive() {
+        let err = ExtractError::AlreadyExists(PathBuf::from("out/a"))
+            .in_entry("a", Some(Path::new("out/a")))
+            .in_archive(Some(Path::new("archive.zip")));
+        assert_eq!(err.to_string(), "a (to out/a): out/a already exists");
+        let err = ExtractError::output_dir(Path::new("out"), io::Error::other("denied"))
+            .in_archive(Some(Path::new("archive.zip")));
+        assert_eq!(err.to_string(), "output directory out: denied");
+    }
+}
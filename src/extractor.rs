@@ -0,0 +1,1350 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use indicatif::ProgressBar;
+use time::OffsetDateTime;
+
+use crate::archive::{self, ArchiveBackend, Entry, Format};
+use crate::attributes;
+use crate::cancel::{CancellableReader, CancellationToken};
+use crate::crc::CrcReader;
+use crate::error::ExtractError;
+use crate::filter::EntryFilter;
+use crate::journal::{self, Journal};
+use crate::limits::{Limits, Usage};
+use crate::overwrite::OverwritePolicy;
+use crate::progress::{self, ProgressReader};
+use crate::security::{FindingKind, LinkGuard, SecurityFinding, SecurityMode};
+use crate::staging::StagingDir;
+
+/// Where the archive is read from.
+pub enum Input {
+    /// A path to an archive on disk.
+    Path(PathBuf),
+    /// An archive file that is already open.
+    File(File),
+    /// A stream that can only be read once from front to back, such as a
+    /// pipe. See [`ZipExtractor::is_streaming`] for what that rules out.
+    Stream(Box<dyn Read>),
+}
+
+impl fmt::Debug for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Input::Path(path) => f.debug_tuple("Path").field(path).finish(),
+            Input::File(file) => f.debug_tuple("File").field(file).finish(),
+            Input::Stream(_) => f.write_str("Stream"),
+        }
+    }
+}
+
+impl From<PathBuf> for Input {
+    fn from(path: PathBuf) -> Self {
+        Input::Path(path)
+    }
+}
+
+impl From<&Path> for Input {
+    fn from(path: &Path) -> Self {
+        Input::Path(path.to_path_buf())
+    }
+}
+
+impl From<&str> for Input {
+    fn from(path: &str) -> Self {
+        Input::Path(PathBuf::from(path))
+    }
+}
+
+impl From<File> for Input {
+    fn from(file: File) -> Self {
+        Input::File(file)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Directory,
+    File {
+        size: u64,
+    },
+    Symlink,
+    /// A tar entry that shares the data of an earlier one.
+    Hardlink,
+}
+
+/// What happened to an entry when it was written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// Not written yet.
+    Pending,
+    Created,
+    Overwritten,
+    /// Left out because of the overwrite policy.
+    Skipped,
+    /// Written under another name because the destination already existed.
+    Renamed(PathBuf),
+    /// Left as an interrupted extraction wrote it; only reported when
+    /// extracting with [`ZipExtractorBuilder::resume`].
+    Resumed,
+    /// Could not be written; [`ExtractedFile::error`] says why. Only reported
+    /// when extracting with [`ZipExtractorBuilder::keep_going`].
+    Failed,
+}
+
+#[derive(Debug)]
+pub struct ExtractedFile {
+    /// The name as stored in the archive.
+    pub name: String,
+    /// Destination of the entry inside the output directory.
+    pub path: PathBuf,
+    pub kind: FileKind,
+    /// What a symlink points to as stored, or for a hardlink the path of the
+    /// file it links to.
+    pub link_target: Option<PathBuf>,
+    /// Position of the entry in the archive.
+    pub index: usize,
+    pub modified: Option<OffsetDateTime>,
+    pub unix_mode: Option<u32>,
+    /// Size of the stored data, if the format records it per entry.
+    pub compressed_size: Option<u64>,
+    /// CRC-32 recorded in the archive, if any.
+    pub crc32: Option<u32>,
+    pub outcome: Outcome,
+    /// Why the entry could not be written, if it [`Failed`](Outcome::Failed).
+    pub error: Option<ExtractError>,
+}
+
+/// Outcome of verifying a single entry with [`ZipExtractor::test`].
+#[derive(Debug)]
+pub struct TestedEntry {
+    pub index: usize,
+    pub name: String,
+    /// Why the entry failed verification, or `None` if it is intact.
+    pub error: Option<ExtractError>,
+}
+
+/// Snapshot of the extraction state handed to the progress callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub entries_done: u64,
+    /// Zero when reading a stream, whose entries are not known in advance.
+    pub entries_total: u64,
+    /// Uncompressed bytes of the finished entries.
+    pub bytes_done: u64,
+    /// Zero when reading a stream.
+    pub bytes_total: u64,
+}
+
+type ProgressCallback = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+/// Settings that shape how entries are written.
+#[derive(Debug, Clone)]
+struct Options {
+    overwrite: OverwritePolicy,
+    security: SecurityMode,
+    limits: Limits,
+    filter: EntryFilter,
+    preserve_permissions: bool,
+    preserve_mtime: bool,
+    jobs: usize,
+    keep_going: bool,
+    atomic: bool,
+    resume: bool,
+    cancel: CancellationToken,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            overwrite: OverwritePolicy::default(),
+            security: SecurityMode::default(),
+            limits: Limits::default(),
+            filter: EntryFilter::default(),
+            preserve_permissions: true,
+            preserve_mtime: true,
+            jobs: 1,
+            keep_going: false,
+            atomic: false,
+            resume: false,
+            cancel: CancellationToken::default(),
+        }
+    }
+}
+
+/// Configures a [`ZipExtractor`]; created with [`ZipExtractor::builder`].
+pub struct ZipExtractorBuilder {
+    input: Input,
+    format: Option<Format>,
+    output_dir: PathBuf,
+    progress: bool,
+    on_progress: Option<ProgressCallback>,
+    password: Option<Vec<u8>>,
+    options: Options,
+}
+
+impl ZipExtractorBuilder {
+    /// Forces the archive format instead of detecting it from the content.
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Directory the entries are extracted to. Defaults to the current directory.
+    pub fn output_dir(mut self, output_dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = output_dir.into();
+        self
+    }
+
+    /// Draw a progress bar on the terminal while extracting.
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// How to handle entries whose destination already exists. Defaults to
+    /// [`OverwritePolicy::Overwrite`].
+    pub fn overwrite(mut self, policy: OverwritePolicy) -> Self {
+        self.options.overwrite = policy;
+        self
+    }
+
+    /// How to treat entries that would be written outside the output
+    /// directory. Defaults to [`SecurityMode::Lenient`].
+    pub fn security(mut self, mode: SecurityMode) -> Self {
+        self.options.security = mode;
+        self
+    }
+
+    /// Resource limits enforced on the declared sizes up front and on the
+    /// decompressed bytes while extracting.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.options.limits = limits;
+        self
+    }
+
+    /// Extracts only the entries the filter matches. The others are not read,
+    /// checked against the limits or counted by the progress bar.
+    pub fn filter(mut self, filter: EntryFilter) -> Self {
+        self.options.filter = filter;
+        self
+    }
+
+    /// Apply the Unix permissions recorded in the archive. On by default.
+    pub fn preserve_permissions(mut self, preserve: bool) -> Self {
+        self.options.preserve_permissions = preserve;
+        self
+    }
+
+    /// Apply the modification times recorded in the archive. On by default.
+    pub fn preserve_mtime(mut self, preserve: bool) -> Self {
+        self.options.preserve_mtime = preserve;
+        self
+    }
+
+    /// Number of threads extracting entries at once. Only zip archives can be
+    /// read in parallel; tar streams are always extracted on one thread.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.options.jobs = jobs.max(1);
+        self
+    }
+
+    /// Go on with the other entries when one cannot be read or written,
+    /// recording the failure in its [`ExtractedFile`] instead of stopping.
+    /// Resource limits and [`OverwritePolicy::Fail`] still stop the
+    /// extraction. Off by default.
+    pub fn keep_going(mut self, keep_going: bool) -> Self {
+        self.options.keep_going = keep_going;
+        self
+    }
+
+    /// Extract into a hidden sibling of the output directory and rename it
+    /// into place only once every entry is written, so the output directory
+    /// never shows a partial tree. On failure the staged tree is removed. The
+    /// output directory must not exist yet or be empty, and an entry failing
+    /// under [`keep_going`](Self::keep_going) fails the whole extraction.
+    /// Off by default.
+    pub fn atomic(mut self, atomic: bool) -> Self {
+        self.options.atomic = atomic;
+        self
+    }
+
+    /// Skip the entries an interrupted extraction into the same output
+    /// directory already wrote. Every extraction keeps a journal of the
+    /// entries it completed in the output directory until all of them are
+    /// written, unless it stopped at a limit, an unsafe entry or corrupt data,
+    /// which a resume would stop at again; resuming trusts an entry from it only if the archive
+    /// describes the entry the same way and the file on disk still has the
+    /// size it was written with. Has no effect with [`atomic`](Self::atomic),
+    /// which leaves nothing behind to resume. Off by default.
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.options.resume = resume;
+        self
+    }
+
+    /// Token that stops the extraction or test when cancelled. The entry being
+    /// written is removed and [`ExtractError::Cancelled`] returned; what was
+    /// written before stays, and the journal lets
+    /// [`resume`](Self::resume) pick up from there.
+    pub fn cancel_token(mut self, token: CancellationToken) -> Self {
+        self.options.cancel = token;
+        self
+    }
+
+    /// Password for encrypted zip entries, using either ZipCrypto or WinZip AES.
+    pub fn password(mut self, password: impl Into<Vec<u8>>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Called after every extracted entry.
+    pub fn on_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        self.on_progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Opens the archive; for zip files this also reads the central directory.
+    pub fn build(self) -> Result<ZipExtractor, ExtractError> {
+        let streaming = matches!(self.input, Input::Stream(_));
+        let archive = match &self.input {
+            Input::Path(path) => Some(path.clone()),
+            _ => None,
+        };
+        let opened = match self.input {
+            Input::Path(path) => File::open(path)
+                .map_err(ExtractError::from)
+                .and_then(|file| open_file(file, self.format)),
+            Input::File(file) => open_file(file, self.format),
+            Input::Stream(reader) => archive::open_stream(reader, self.format)
+                .map(|(format, backend)| (format, 0, backend)),
+        };
+        let (format, archive_len, mut backend) =
+            opened.map_err(|err| err.in_archive(archive.as_deref()))?;
+        backend.set_password(self.password);
+        let progress_bar = if self.progress {
+            Some(progress::progress_bar(!streaming))
+        } else {
+            None
+        };
+        Ok(ZipExtractor {
+            backend,
+            archive,
+            format,
+            archive_len,
+            streaming,
+            output_dir: self.output_dir,
+            progress_bar,
+            on_progress: self.on_progress,
+            options: self.options,
+            findings: Vec::new(),
+        })
+    }
+}
+
+/// Detects the format of an archive on disk unless it is given, and returns
+/// it with the length of the file.
+fn open_file(
+    mut file: File,
+    format: Option<Format>,
+) -> Result<(Format, u64, Box<dyn ArchiveBackend>), ExtractError> {
+    let format = match format {
+        Some(format) => format,
+        None => archive::detect(&mut file)?,
+    };
+    let archive_len = file.metadata()?.len();
+    Ok((format, archive_len, archive::open(file, format)?))
+}
+
+pub struct ZipExtractor {
+    backend: Box<dyn ArchiveBackend>,
+    /// Path of the archive, for the errors it causes.
+    archive: Option<PathBuf>,
+    format: Format,
+    archive_len: u64,
+    streaming: bool,
+    output_dir: PathBuf,
+    progress_bar: Option<ProgressBar>,
+    on_progress: Option<ProgressCallback>,
+    options: Options,
+    findings: Vec<SecurityFinding>,
+}
+
+impl ZipExtractor {
+    pub fn builder(input: impl Into<Input>) -> ZipExtractorBuilder {
+        ZipExtractorBuilder {
+            input: input.into(),
+            format: None,
+            output_dir: PathBuf::from("."),
+            progress: false,
+            on_progress: None,
+            password: None,
+            options: Options::default(),
+        }
+    }
+
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Whether the archive is read from a stream. A stream is read only once:
+    /// either [`entries`](Self::entries), [`extract`](Self::extract) or
+    /// [`test`](Self::test) can be called, and extraction checks each entry
+    /// as it arrives, so strict mode stops at the first unsafe entry instead
+    /// of before anything is written. Zip entries in a stream carry no Unix
+    /// mode and cannot be decrypted.
+    pub fn is_streaming(&self) -> bool {
+        self.streaming
+    }
+
+    /// Replaces the password given to the builder, e.g. after prompting for
+    /// one because [`Entry::encryption`] showed encrypted entries.
+    pub fn set_password(&mut self, password: Option<Vec<u8>>) {
+        self.backend.set_password(password);
+    }
+
+    /// Entries the last extraction refused as unsafe.
+    pub fn security_findings(&self) -> &[SecurityFinding] {
+        &self.findings
+    }
+
+    /// Reads the metadata of every entry without extracting anything.
+    pub fn entries(&mut self) -> Result<Vec<Entry>, ExtractError> {
+        let result = self.backend.entries();
+        self.in_archive(result)
+    }
+
+    pub fn extract(&mut self) -> Result<Vec<ExtractedFile>, ExtractError> {
+        let result = if self.options.atomic {
+            self.extract_atomically()
+        } else {
+            self.extract_entries()
+        };
+        self.abandon_if_cancelled(&result);
+        self.in_archive(result)
+    }
+
+    fn extract_entries(&mut self) -> Result<Vec<ExtractedFile>, ExtractError> {
+        if self.streaming {
+            self.extract_stream()
+        } else {
+            self.extract_file()
+        }
+    }
+
+    /// Extracts into a staging directory that replaces the output directory
+    /// only if every entry was written.
+    fn extract_atomically(&mut self) -> Result<Vec<ExtractedFile>, ExtractError> {
+        let staging_dir = StagingDir::create(&self.output_dir)?;
+        let output_dir = std::mem::replace(&mut self.output_dir, staging_dir.path().to_path_buf());
+        let result = self.extract_entries().and_then(|mut extracted_files| {
+            match extracted_files
+                .iter_mut()
+                .find_map(|extracted_file| extracted_file.error.take())
+            {
+                Some(err) => Err(err),
+                None => Ok(extracted_files),
+            }
+        });
+        self.output_dir = output_dir;
+        match result {
+            Ok(mut extracted_files) => {
+                staging_dir.commit(&mut extracted_files)?;
+                Ok(extracted_files)
+            }
+            Err(err) => {
+                let err = staging_dir.rebase_error(err);
+                staging_dir.discard();
+                Err(err)
+            }
+        }
+    }
+
+    /// Decompresses every entry without writing it anywhere, checking the
+    /// CRC-32 and the declared size.
+    pub fn test(&mut self) -> Result<Vec<TestedEntry>, ExtractError> {
+        let result = if self.streaming {
+            self.test_stream()
+        } else {
+            self.test_file()
+        };
+        self.abandon_if_cancelled(&result);
+        self.in_archive(result)
+    }
+
+    /// Writes the data of the entries stored under `names` to `out`, one after
+    /// the other in the order given, without touching the file system. Names
+    /// are matched exactly as stored in the archive. A stream can only be read
+    /// once, so its entries come out in archive order instead.
+    pub fn cat(
+        &mut self,
+        names: &[impl AsRef<str>],
+        out: &mut dyn Write,
+    ) -> Result<(), ExtractError> {
+        let result = if self.streaming {
+            self.cat_stream(names, out)
+        } else {
+            self.cat_file(names, out)
+        };
+        self.in_archive(result)
+    }
+
+    fn in_archive<T>(&self, result: Result<T, ExtractError>) -> Result<T, ExtractError> {
+        result.map_err(|err| err.in_archive(self.archive.as_deref()))
+    }
+
+    fn extract_file(&mut self) -> Result<Vec<ExtractedFile>, ExtractError> {
+        let entries = self.backend.entries()?;
+        let mut extracted_files = self.get_extracted_files(&entries)?;
+        let journal = Journal::open(&self.output_dir, self.options.resume)?;
+        journal.resume(&mut extracted_files);
+        if let Err(err) = self.write_extracted_files(&entries, &mut extracted_files, &journal) {
+            if let ExtractError::LimitExceeded { .. } = err.root_cause() {
+                remove_written_files(&extracted_files);
+            }
+            journal.abort(&err);
+            return Err(err);
+        }
+        self.apply_directory_attributes(&extracted_files)?;
+        journal.finish(&extracted_files)?;
+        self.finish_progress_bar(extracted_message(&extracted_files));
+        Ok(extracted_files)
+    }
+
+    fn test_file(&mut self) -> Result<Vec<TestedEntry>, ExtractError> {
+        let entries = self.backend.entries()?;
+        let by_index = entries
+            .iter()
+            .map(|entry| (entry.index, entry))
+            .collect::<HashMap<_, _>>();
+        let visited = entries.iter().collect::<Vec<_>>();
+        let cancel = self.options.cancel.clone();
+
+        let mut tested_entries = Vec::with_capacity(entries.len());
+        self.visit_entries(&visited, |index, reader| {
+            let entry = by_index[&index];
+            let verified = reader.and_then(|reader| {
+                verify_entry(entry, &mut CancellableReader::new(reader, &cancel))
+            });
+            if cancel.is_cancelled() {
+                return Err(ExtractError::Cancelled);
+            }
+            tested_entries.push(TestedEntry {
+                index,
+                name: entry.name.clone(),
+                error: verified.err(),
+            });
+            Ok(())
+        })?;
+        self.finish_progress_bar(format!("Tested {} files", tested_entries.len()));
+        Ok(tested_entries)
+    }
+
+    fn cat_file(
+        &mut self,
+        names: &[impl AsRef<str>],
+        out: &mut dyn Write,
+    ) -> Result<(), ExtractError> {
+        for name in names {
+            let name = name.as_ref();
+            self.backend
+                .read_by_name(name, &mut |reader| {
+                    io::copy(reader, out)
+                        .map_err(|err| ExtractError::from(err).in_entry(name, None))?;
+                    Ok(())
+                })
+                .map_err(|err| match err {
+                    // These name the entry already.
+                    ExtractError::EntryNotFound(_) | ExtractError::IsDirectory(_) => err,
+                    err => err.in_entry(name, None),
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Plans where each entry the filter selects goes. Unsafe entries become
+    /// findings; in strict mode they fail the extraction before anything is
+    /// written, as does an archive whose declared sizes exceed the limits.
+    fn get_extracted_files(
+        &mut self,
+        entries: &[Entry],
+    ) -> Result<Vec<ExtractedFile>, ExtractError> {
+        let mut findings = Vec::new();
+        let mut safe_entries = Vec::new();
+        let mut extracted_files = Vec::new();
+        let mut link_guard = LinkGuard::default();
+        for entry in entries {
+            if !self.options.filter.matches(entry) {
+                continue;
+            }
+            let (enclosed_name, link_target) = match check_entry(entry, &link_guard) {
+                Ok(checked) => checked,
+                Err(kind) => {
+                    findings.push(SecurityFinding {
+                        index: entry.index,
+                        name: entry.name.clone(),
+                        kind,
+                    });
+                    continue;
+                }
+            };
+            if entry.kind == FileKind::Symlink {
+                link_guard.add_symlink(enclosed_name);
+            }
+            safe_entries.push(entry);
+            extracted_files.push(plan_entry(
+                &self.output_dir,
+                entry,
+                enclosed_name,
+                link_target,
+            ));
+        }
+
+        self.findings = findings;
+        if self.options.security == SecurityMode::Strict && !self.findings.is_empty() {
+            return Err(ExtractError::UnsafeEntries(self.findings.clone()));
+        }
+        self.options
+            .limits
+            .check_entries(&safe_entries, self.archive_len)?;
+        Ok(extracted_files)
+    }
+
+    fn write_extracted_files(
+        &mut self,
+        entries: &[Entry],
+        extracted_files: &mut [ExtractedFile],
+        journal: &Journal,
+    ) -> Result<(), ExtractError> {
+        let slots = extracted_files
+            .iter()
+            .enumerate()
+            .filter(|(_, extracted_file)| extracted_file.outcome == Outcome::Pending)
+            .map(|(slot, extracted_file)| (extracted_file.index, slot))
+            .collect::<HashMap<_, _>>();
+        let visited = entries
+            .iter()
+            .filter(|entry| slots.contains_key(&entry.index))
+            .collect::<Vec<_>>();
+        let usage = Usage::new(self.options.limits, Some(self.archive_len), None);
+        if self.options.jobs > 1 {
+            let backends = (0..self.options.jobs)
+                .map(|_| self.backend.try_clone())
+                .collect::<Option<Vec<_>>>();
+            if let Some(backends) = backends {
+                return self.write_in_parallel(
+                    backends,
+                    &visited,
+                    extracted_files,
+                    &slots,
+                    &usage,
+                    journal,
+                );
+            }
+        }
+        let options = self.options.clone();
+        self.visit_entries(&visited, |index, reader| {
+            let extracted_file = &mut extracted_files[slots[&index]];
+            let written = write_entry(extracted_file, reader, &usage, &options, journal);
+            record(extracted_file, written, &options)
+        })
+    }
+
+    /// Extracts on one thread per backend. Directories are created up front
+    /// and links at the end, both on this thread; the files in between are
+    /// handed out in archive order. If entries fail, the error of the first
+    /// of them in archive order is returned, whichever thread hit it first.
+    fn write_in_parallel(
+        &self,
+        backends: Vec<Box<dyn ArchiveBackend + Send>>,
+        entries: &[&Entry],
+        extracted_files: &mut [ExtractedFile],
+        slots: &HashMap<usize, usize>,
+        usage: &Usage,
+        journal: &Journal,
+    ) -> Result<(), ExtractError> {
+        let options = &self.options;
+        let tracker = Tracker::new(
+            self.progress_bar.as_ref(),
+            self.on_progress.as_ref(),
+            entries,
+        );
+        let (files, others): (Vec<&Entry>, Vec<&Entry>) = entries
+            .iter()
+            .partition(|entry| matches!(entry.kind, FileKind::File { .. }));
+        let (directories, links): (Vec<&Entry>, Vec<&Entry>) = others
+            .into_iter()
+            .partition(|entry| entry.kind == FileKind::Directory);
+
+        let mut write_here = |entry: &Entry| {
+            let extracted_file = &mut extracted_files[slots[&entry.index]];
+            tracker.visit(entry, Ok(&mut io::empty()), |reader| {
+                let written = write_entry(extracted_file, reader, usage, options, journal);
+                record(extracted_file, written, options)
+            })
+        };
+        for entry in directories {
+            write_here(entry)?;
+        }
+
+        let next = AtomicUsize::new(0);
+        let first_failure = AtomicUsize::new(usize::MAX);
+        let planned: &[ExtractedFile] = extracted_files;
+        let mut results = thread::scope(|scope| {
+            let workers = backends
+                .into_iter()
+                .map(|mut backend| {
+                    let (files, tracker, next, first_failure) =
+                        (&files, &tracker, &next, &first_failure);
+                    scope.spawn(move || {
+                        let mut results = Vec::new();
+                        loop {
+                            let position = next.fetch_add(1, Ordering::SeqCst);
+                            if position >= files.len()
+                                || position > first_failure.load(Ordering::SeqCst)
+                            {
+                                return results;
+                            }
+                            let entry = files[position];
+                            let extracted_file = &planned[slots[&entry.index]];
+                            let mut written = Ok(Outcome::Pending);
+                            let result = backend.read_entries(&[entry.index], &mut |_, reader| {
+                                tracker.visit(entry, reader, |reader| {
+                                    written = write_entry(
+                                        extracted_file,
+                                        reader,
+                                        usage,
+                                        options,
+                                        journal,
+                                    );
+                                    Ok(())
+                                })
+                            });
+                            let result = result.and(written);
+                            if result.as_ref().is_err_and(|err| stops(err, options)) {
+                                first_failure.fetch_min(position, Ordering::SeqCst);
+                            }
+                            results.push((position, result));
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .flat_map(|worker| {
+                    worker
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect::<Vec<_>>()
+        });
+
+        results.sort_by_key(|(position, _)| *position);
+        let mut first_error = None;
+        for (position, result) in results {
+            let extracted_file = &mut extracted_files[slots[&files[position].index]];
+            if let Err(err) = record(extracted_file, result, options) {
+                first_error.get_or_insert(err);
+            }
+        }
+        if let Some(err) = first_error {
+            return Err(err);
+        }
+
+        let mut write_here = |entry: &Entry| {
+            let extracted_file = &mut extracted_files[slots[&entry.index]];
+            tracker.visit(entry, Ok(&mut io::empty()), |reader| {
+                let written = write_entry(extracted_file, reader, usage, options, journal);
+                record(extracted_file, written, options)
+            })
+        };
+        for entry in links {
+            write_here(entry)?;
+        }
+        Ok(())
+    }
+
+    /// Extracts an archive read from a stream, checking each entry as it
+    /// arrives rather than planning them all up front.
+    fn extract_stream(&mut self) -> Result<Vec<ExtractedFile>, ExtractError> {
+        let options = &self.options;
+        let output_dir = &self.output_dir;
+        let usage = Usage::new(options.limits, None, self.backend.compressed_read());
+        let tracker = Tracker::new(self.progress_bar.as_ref(), self.on_progress.as_ref(), &[]);
+        let journal = Journal::open(output_dir, options.resume)?;
+        let mut findings = Vec::new();
+        let mut link_guard = LinkGuard::default();
+        let mut extracted_files = Vec::new();
+        let result = self.backend.stream_entries(&mut |entry, reader| {
+            if !options.filter.matches(entry) {
+                return Ok(());
+            }
+            let (enclosed_name, link_target) = match check_entry(entry, &link_guard) {
+                Ok(checked) => checked,
+                Err(kind) => {
+                    findings.push(SecurityFinding {
+                        index: entry.index,
+                        name: entry.name.clone(),
+                        kind,
+                    });
+                    if options.security == SecurityMode::Strict {
+                        return Err(ExtractError::UnsafeEntries(findings.clone()));
+                    }
+                    return Ok(());
+                }
+            };
+            options
+                .limits
+                .check_streamed_entry(entry, extracted_files.len() as u64 + 1)?;
+            if entry.kind == FileKind::Symlink {
+                link_guard.add_symlink(enclosed_name);
+            }
+            let mut extracted_file = plan_entry(output_dir, entry, enclosed_name, link_target);
+            if journal.is_complete(&extracted_file) {
+                // The backend skips the data left unread.
+                extracted_file.outcome = Outcome::Resumed;
+                tracker.visit(entry, Ok(&mut io::empty()), |_| Ok(()))?;
+                extracted_files.push(extracted_file);
+                return Ok(());
+            }
+            tracker.visit(entry, reader, |reader| {
+                let written = write_entry(&extracted_file, reader, &usage, options, &journal);
+                record(&mut extracted_file, written, options)
+            })?;
+            extracted_files.push(extracted_file);
+            Ok(())
+        });
+        self.findings = findings;
+        if let Err(err) = result {
+            if let ExtractError::LimitExceeded { .. } = err.root_cause() {
+                remove_written_files(&extracted_files);
+            }
+            journal.abort(&err);
+            return Err(err);
+        }
+        self.apply_directory_attributes(&extracted_files)?;
+        journal.finish(&extracted_files)?;
+        self.finish_progress_bar(extracted_message(&extracted_files));
+        Ok(extracted_files)
+    }
+
+    fn cat_stream(
+        &mut self,
+        names: &[impl AsRef<str>],
+        out: &mut dyn Write,
+    ) -> Result<(), ExtractError> {
+        let mut missing = names.iter().map(AsRef::as_ref).collect::<Vec<_>>();
+        self.backend.stream_entries(&mut |entry, reader| {
+            let name = entry.name.trim_end_matches('/');
+            if !names
+                .iter()
+                .any(|wanted| wanted.as_ref().trim_end_matches('/') == name)
+            {
+                return Ok(());
+            }
+            if entry.kind == FileKind::Directory {
+                return Err(ExtractError::IsDirectory(name.to_string()));
+            }
+            reader
+                .and_then(|reader| Ok(io::copy(reader, out)?))
+                .map_err(|err| err.in_entry(name, None))?;
+            missing.retain(|wanted| wanted.trim_end_matches('/') != name);
+            Ok(())
+        })?;
+        match missing.first() {
+            Some(name) => Err(ExtractError::EntryNotFound(name.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Tests an archive read from a stream. The stream backends check each
+    /// entry against the CRC-32 and sizes they find themselves, which for
+    /// zip entries with a data descriptor only follow the data.
+    fn test_stream(&mut self) -> Result<Vec<TestedEntry>, ExtractError> {
+        let tracker = Tracker::new(self.progress_bar.as_ref(), self.on_progress.as_ref(), &[]);
+        let cancel = &self.options.cancel;
+        let mut tested_entries = Vec::new();
+        self.backend.stream_entries(&mut |entry, reader| {
+            tracker.visit(entry, reader, |reader| {
+                let copied = reader.and_then(|reader| {
+                    let mut reader = CancellableReader::new(reader, cancel);
+                    Ok(io::copy(&mut reader, &mut io::sink())?)
+                });
+                if cancel.is_cancelled() {
+                    return Err(ExtractError::Cancelled);
+                }
+                tested_entries.push(TestedEntry {
+                    index: entry.index,
+                    name: entry.name.clone(),
+                    error: copied.err(),
+                });
+                Ok(())
+            })
+        })?;
+        self.finish_progress_bar(format!("Tested {} files", tested_entries.len()));
+        Ok(tested_entries)
+    }
+
+    /// Directories get their attributes once everything is written, since
+    /// writing into them changes their modification time and a read-only
+    /// mode would keep their contents from being written. Deeper directories
+    /// go first so setting a parent does not disturb its children.
+    fn apply_directory_attributes(
+        &self,
+        extracted_files: &[ExtractedFile],
+    ) -> Result<(), ExtractError> {
+        let mut directories = extracted_files
+            .iter()
+            .filter(|extracted_file| {
+                extracted_file.kind == FileKind::Directory
+                    && extracted_file.outcome == Outcome::Created
+            })
+            .collect::<Vec<_>>();
+        directories.sort_by_key(|extracted_file| {
+            std::cmp::Reverse(extracted_file.path.components().count())
+        });
+        for extracted_file in directories {
+            apply_attributes(&extracted_file.path, extracted_file, &self.options)
+                .map_err(|err| err.in_entry(&extracted_file.name, Some(&extracted_file.path)))?;
+        }
+        Ok(())
+    }
+
+    /// Feeds `entries`, in archive order, to `visit` while tracking progress.
+    fn visit_entries<F>(&mut self, entries: &[&Entry], mut visit: F) -> Result<(), ExtractError>
+    where
+        F: FnMut(usize, Result<&mut dyn Read, ExtractError>) -> Result<(), ExtractError>,
+    {
+        let by_index = entries
+            .iter()
+            .map(|entry| (entry.index, *entry))
+            .collect::<HashMap<_, _>>();
+        let indices = entries.iter().map(|entry| entry.index).collect::<Vec<_>>();
+        let tracker = Tracker::new(
+            self.progress_bar.as_ref(),
+            self.on_progress.as_ref(),
+            entries,
+        );
+        self.backend.read_entries(&indices, &mut |index, reader| {
+            tracker.visit(by_index[&index], reader, |reader| visit(index, reader))
+        })
+    }
+
+    fn finish_progress_bar(&mut self, message: String) {
+        if let Some(pb) = &mut self.progress_bar {
+            pb.finish_with_message(message);
+        }
+    }
+
+    /// Leaves the progress bar where it stopped when the run was cancelled.
+    fn abandon_if_cancelled<T>(&mut self, result: &Result<T, ExtractError>) {
+        if let (Some(pb), Err(ExtractError::Cancelled)) = (&mut self.progress_bar, result) {
+            pb.abandon_with_message("Cancelled");
+        }
+    }
+}
+
+/// Progress of reading a set of entries, possibly from several threads. The
+/// progress bar advances with the bytes read and shows the current entry; the
+/// progress callback runs after each entry.
+struct Tracker<'a> {
+    progress_bar: Option<&'a ProgressBar>,
+    on_progress: Option<&'a ProgressCallback>,
+    progress: Mutex<Progress>,
+}
+
+impl<'a> Tracker<'a> {
+    fn new(
+        progress_bar: Option<&'a ProgressBar>,
+        on_progress: Option<&'a ProgressCallback>,
+        entries: &[&Entry],
+    ) -> Self {
+        let progress = Progress {
+            entries_done: 0,
+            entries_total: entries.len() as u64,
+            bytes_done: 0,
+            // Declared sizes can add up to more than fits, so the total stops
+            // at the largest value instead.
+            bytes_total: entries
+                .iter()
+                .fold(0, |total, entry| total.saturating_add(entry.size)),
+        };
+        if let Some(pb) = progress_bar {
+            pb.set_length(progress.bytes_total);
+        }
+        Self {
+            progress_bar,
+            on_progress,
+            progress: Mutex::new(progress),
+        }
+    }
+
+    fn visit<F>(
+        &self,
+        entry: &Entry,
+        reader: Result<&mut dyn Read, ExtractError>,
+        visit: F,
+    ) -> Result<(), ExtractError>
+    where
+        F: FnOnce(Result<&mut dyn Read, ExtractError>) -> Result<(), ExtractError>,
+    {
+        if let Some(pb) = self.progress_bar {
+            pb.set_message(entry.name.clone());
+        }
+        let read = match reader {
+            Ok(reader) => {
+                let mut reader = ProgressReader::new(reader, self.progress_bar);
+                visit(Ok(&mut reader))?;
+                reader.len()
+            }
+            Err(err) => {
+                visit(Err(err))?;
+                0
+            }
+        };
+
+        // Entries that were skipped or not read to the end still count in
+        // full, keeping the bar in step with the declared sizes.
+        if let Some(pb) = self.progress_bar {
+            pb.inc(entry.size.saturating_sub(read));
+        }
+        let mut progress = self.progress.lock().unwrap();
+        progress.entries_done += 1;
+        progress.bytes_done = progress.bytes_done.saturating_add(entry.size.max(read));
+        if let Some(callback) = self.on_progress {
+            callback(&progress);
+        }
+        Ok(())
+    }
+}
+
+/// Describes where a checked entry goes. Hardlink targets are resolved
+/// against the output directory like the entry itself.
+fn plan_entry(
+    output_dir: &Path,
+    entry: &Entry,
+    enclosed_name: &Path,
+    link_target: Option<PathBuf>,
+) -> ExtractedFile {
+    ExtractedFile {
+        name: entry.name.clone(),
+        path: output_dir.join(enclosed_name),
+        kind: entry.kind,
+        link_target: link_target.map(|target| match entry.kind {
+            FileKind::Hardlink => output_dir.join(target),
+            _ => target,
+        }),
+        index: entry.index,
+        modified: entry.modified,
+        unix_mode: entry.unix_mode,
+        compressed_size: entry.compressed_size,
+        crc32: entry.crc32,
+        outcome: Outcome::Pending,
+        error: None,
+    }
+}
+
+/// Writes a single entry to its planned path and reports how that went.
+/// Errors name the entry and its destination.
+fn write_entry(
+    extracted_file: &ExtractedFile,
+    reader: Result<&mut dyn Read, ExtractError>,
+    usage: &Usage,
+    options: &Options,
+    journal: &Journal,
+) -> Result<Outcome, ExtractError> {
+    if options.cancel.is_cancelled() {
+        return Err(ExtractError::Cancelled);
+    }
+    reader
+        .and_then(|reader| {
+            let outcome = create_entry(extracted_file, reader, usage, options)?;
+            if matches!(outcome, Outcome::Created | Outcome::Overwritten) {
+                journal.complete(extracted_file)?;
+            }
+            Ok(outcome)
+        })
+        .map_err(|err| err.in_entry(&extracted_file.name, Some(&extracted_file.path)))
+}
+
+fn create_entry(
+    extracted_file: &ExtractedFile,
+    reader: &mut dyn Read,
+    usage: &Usage,
+    options: &Options,
+) -> Result<Outcome, ExtractError> {
+    if extracted_file.kind == FileKind::Directory {
+        let dir_path = &extracted_file.path;
+        if !dir_path.exists() {
+            fs::create_dir_all(dir_path)?;
+        }
+        return Ok(Outcome::Created);
+    }
+
+    let outcome = options
+        .overwrite
+        .resolve(&extracted_file.path, extracted_file.modified)?;
+    let outpath = match &outcome {
+        Outcome::Skipped => return Ok(outcome),
+        Outcome::Renamed(path) => path,
+        _ => &extracted_file.path,
+    };
+    if let Some(parent) = outpath.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if outcome == Outcome::Overwritten {
+        // Replace what is there instead of writing through a link or into
+        // the data of a hardlinked file.
+        fs::remove_file(outpath)?;
+    }
+    match (extracted_file.kind, &extracted_file.link_target) {
+        (FileKind::Symlink, Some(target)) => attributes::create_symlink(target, outpath)?,
+        (FileKind::Hardlink, Some(target)) => fs::hard_link(target, outpath)?,
+        (FileKind::Symlink | FileKind::Hardlink, None) => {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidData, "link target cannot be read").into(),
+            );
+        }
+        _ => {
+            let mut outfile = fs::File::create(outpath)?;
+            let reader = CancellableReader::new(reader, &options.cancel);
+            let mut reader = usage.reader(reader, extracted_file.compressed_size);
+            if let Err(err) = io::copy(&mut reader, &mut outfile) {
+                let err = match reader.exceeded() {
+                    Some(exceeded) => exceeded,
+                    None if options.cancel.is_cancelled() => ExtractError::Cancelled,
+                    None => err.into(),
+                };
+                drop(outfile);
+                let _ = fs::remove_file(outpath);
+                return Err(err);
+            }
+        }
+    }
+    apply_attributes(outpath, extracted_file, options)?;
+    Ok(outcome)
+}
+
+fn extracted_message(extracted_files: &[ExtractedFile]) -> String {
+    let failed = extracted_files
+        .iter()
+        .filter(|extracted_file| extracted_file.outcome == Outcome::Failed)
+        .count();
+    match failed {
+        0 => format!("Extracted {} files", extracted_files.len()),
+        _ => format!(
+            "Extracted {} files, {} failed",
+            extracted_files.len() - failed,
+            failed
+        ),
+    }
+}
+
+/// Stores how writing an entry went. With `keep_going` a failure is kept
+/// with the entry instead of returned, unless it [`stops`] the extraction.
+fn record(
+    extracted_file: &mut ExtractedFile,
+    written: Result<Outcome, ExtractError>,
+    options: &Options,
+) -> Result<(), ExtractError> {
+    match written {
+        Ok(outcome) => extracted_file.outcome = outcome,
+        Err(err) if !stops(&err, options) => {
+            extracted_file.outcome = Outcome::Failed;
+            extracted_file.error = Some(err);
+        }
+        Err(err) => return Err(err),
+    }
+    Ok(())
+}
+
+/// Whether a failed entry ends the whole extraction. Exceeding a limit,
+/// meeting an existing file under [`OverwritePolicy::Fail`] or being
+/// cancelled always does.
+fn stops(err: &ExtractError, options: &Options) -> bool {
+    !options.keep_going
+        || matches!(
+            err.root_cause(),
+            ExtractError::LimitExceeded { .. }
+                | ExtractError::AlreadyExists(_)
+                | ExtractError::Cancelled
+        )
+}
+
+/// Returns where an entry goes relative to the output directory and, for
+/// links, the safe target. Hardlink targets come back relative to the output
+/// directory as well.
+fn check_entry<'a>(
+    entry: &'a Entry,
+    link_guard: &LinkGuard,
+) -> Result<(&'a Path, Option<PathBuf>), FindingKind> {
+    let enclosed_name = entry
+        .enclosed_name
+        .as_deref()
+        .ok_or(FindingKind::PathTraversal)?;
+    if journal::is_journal(enclosed_name) {
+        return Err(FindingKind::ReservedName);
+    }
+    if link_guard.goes_through_link(enclosed_name) {
+        return Err(FindingKind::WriteThroughLink);
+    }
+    let link_target = match (entry.kind, &entry.link_target) {
+        (FileKind::Symlink, Some(target)) => {
+            if link_guard.symlink_escapes(enclosed_name, target) {
+                return Err(FindingKind::LinkEscape);
+            }
+            Some(target.clone())
+        }
+        (FileKind::Hardlink, Some(target)) => Some(
+            link_guard
+                .hardlink_target(target)
+                .ok_or(FindingKind::LinkEscape)?,
+        ),
+        _ => None,
+    };
+    Ok((enclosed_name, link_target))
+}
+
+fn apply_attributes(
+    path: &Path,
+    extracted_file: &ExtractedFile,
+    options: &Options,
+) -> Result<(), ExtractError> {
+    match extracted_file.kind {
+        // A hardlink shares the attributes of the file it links to.
+        FileKind::Hardlink => return Ok(()),
+        // Permissions of a symlink are not used, so only its own time is set.
+        FileKind::Symlink => {
+            if let (true, Some(modified)) = (options.preserve_mtime, extracted_file.modified) {
+                attributes::set_link_modified(path, modified)?;
+            }
+            return Ok(());
+        }
+        _ => (),
+    }
+    if options.preserve_permissions {
+        if let Some(mode) = extracted_file.unix_mode {
+            attributes::set_permissions(path, mode)?;
+        }
+    }
+    if options.preserve_mtime {
+        if let Some(modified) = extracted_file.modified {
+            attributes::set_modified(path, modified)?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes the files an aborted extraction has created. Files it overwrote
+/// are gone either way and stay as they are.
+fn remove_written_files(extracted_files: &[ExtractedFile]) {
+    for extracted_file in extracted_files {
+        let path = match &extracted_file.outcome {
+            Outcome::Created if extracted_file.kind != FileKind::Directory => &extracted_file.path,
+            Outcome::Renamed(path) => path,
+            _ => continue,
+        };
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Reads an entry to the end and compares it against its metadata.
+fn verify_entry(entry: &Entry, reader: &mut dyn Read) -> Result<(), ExtractError> {
+    let mut reader = CrcReader::new(reader);
+    let copied = io::copy(&mut reader, &mut io::sink());
+    // The zip reader fails at the end of an entry with a bad checksum; report
+    // the mismatch itself rather than that I/O error.
+    if let Some(expected) = entry.crc32 {
+        if reader.len() == entry.size && reader.crc32() != expected {
+            return Err(ExtractError::CrcMismatch {
+                expected,
+                actual: reader.crc32(),
+            });
+        }
+    }
+    copied?;
+    if reader.len() != entry.size {
+        return Err(ExtractError::SizeMismatch {
+            expected: entry.size,
+            actual: reader.len(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use zip::write::FileOptions;
+
+    use super::*;
+    use crate::test_util::{scratch_dir, zip64_archive};
+
+    #[test]
+    fn progress_saturates_on_sizes_too_large_to_add() {
+        let dir = scratch_dir("extractor-progress");
+        let archive = dir.join("big.zip");
+        fs::write(&archive, zip64_archive(&[1 << 63, 1 << 63])).unwrap();
+        let last = Arc::new(Mutex::new(None));
+        let seen = Arc::clone(&last);
+        let tested_entries = ZipExtractor::builder(archive.as_path())
+            .on_progress(move |progress| *seen.lock().unwrap() = Some(*progress))
+            .build()
+            .unwrap()
+            .test()
+            .unwrap();
+        // The entries hold far less than they declare.
+        assert!(tested_entries.iter().all(|entry| entry.error.is_some()));
+        let progress = last.lock().unwrap().unwrap();
+        assert_eq!(progress.entries_done, 2);
+        assert_eq!(progress.bytes_done, u64::MAX);
+        assert_eq!(progress.bytes_total, u64::MAX);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn entries_that_cannot_be_opened_are_named() {
+        let dir = scratch_dir("extractor-cat-method");
+        let archive = dir.join("m97.zip");
+        let mut zip = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        zip.start_file("a", options).unwrap();
+        zip.write_all(b"data").unwrap();
+        let mut bytes = zip.finish().unwrap().into_inner();
+        // Method 97 in the local header and in the central directory.
+        bytes[8] = 97;
+        let central = bytes
+            .windows(4)
+            .position(|window| window == 0x0201_4b50u32.to_le_bytes())
+            .unwrap();
+        bytes[central + 10] = 97;
+        fs::write(&archive, bytes).unwrap();
+
+        let err = ZipExtractor::builder(archive.as_path())
+            .build()
+            .unwrap()
+            .cat(&["a"], &mut io::sink())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{}: a: unsupported compression method 97",
+                archive.display()
+            )
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+use std::path::Path;
+
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+
+use crate::archive::Entry;
+use crate::error::ExtractError;
+
+/// Chooses which entries are extracted, or which files are added to a new
+/// archive, by glob patterns on their names.
+///
+/// `*` and `?` stop at `/`, while `**` matches any number of directories, so
+/// `**/*.json` finds JSON files at any depth. A pattern that matches a
+/// directory selects everything below it. With no include patterns every
+/// entry is included; exclude patterns win over include patterns.
+#[derive(Debug, Clone, Default)]
+pub struct EntryFilter {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+}
+
+/// Configures an [`EntryFilter`]; created with [`EntryFilter::builder`].
+#[derive(Debug, Clone, Default)]
+pub struct EntryFilterBuilder {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    case_insensitive: bool,
+}
+
+impl EntryFilterBuilder {
+    /// Extracts entries matching `pattern`, and only those.
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+
+    /// Leaves out entries matching `pattern`.
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Match names regardless of case. Off by default.
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Compiles the patterns, failing with [`ExtractError::InvalidPattern`]
+    /// on the first one that is not a valid glob.
+    pub fn build(self) -> Result<EntryFilter, ExtractError> {
+        Ok(EntryFilter {
+            include: glob_set(&self.include, self.case_insensitive)?,
+            exclude: glob_set(&self.exclude, self.case_insensitive)?,
+        })
+    }
+}
+
+fn glob_set(patterns: &[String], case_insensitive: bool) -> Result<Option<GlobSet>, ExtractError> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut set = GlobSetBuilder::new();
+    for pattern in patterns {
+        set.add(glob(pattern, case_insensitive)?);
+    }
+    Ok(Some(set.build().map_err(invalid_pattern)?))
+}
+
+fn glob(pattern: &str, case_insensitive: bool) -> Result<Glob, ExtractError> {
+    // Entry names never start with ./ or end with /, so neither should the
+    // patterns written to match them.
+    let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+    GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .case_insensitive(case_insensitive)
+        .build()
+        .map_err(invalid_pattern)
+}
+
+fn invalid_pattern(err: globset::Error) -> ExtractError {
+    ExtractError::InvalidPattern {
+        pattern: err.glob().unwrap_or_default().to_string(),
+        reason: err.kind().to_string(),
+    }
+}
+
+impl EntryFilter {
+    pub fn builder() -> EntryFilterBuilder {
+        EntryFilterBuilder::default()
+    }
+
+    /// Whether `entry` should be extracted. Unsafe names are matched as
+    /// stored, so that they can still be reported when selected.
+    pub fn matches(&self, entry: &Entry) -> bool {
+        match &entry.enclosed_name {
+            Some(name) => self.matches_path(name),
+            None => self.matches_path(Path::new(&entry.name)),
+        }
+    }
+
+    /// Whether a file stored under `name` should be added to an archive.
+    pub(crate) fn matches_name(&self, name: &str) -> bool {
+        self.matches_path(Path::new(name))
+    }
+
+    fn matches_path(&self, path: &Path) -> bool {
+        // Drops the trailing / of directory names.
+        let path = path.components().as_path();
+        let matches = |set: &GlobSet| {
+            path.ancestors()
+                .take_while(|ancestor| !ancestor.as_os_str().is_empty())
+                .any(|ancestor| set.is_match(ancestor))
+        };
+        self.include.as_ref().is_none_or(matches) && !self.exclude.as_ref().is_some_and(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::file_entry;
+
+    fn filter(include: &[&str], exclude: &[&str]) -> EntryFilter {
+        let builder = include
+            .iter()
+            .fold(EntryFilter::builder(), |builder, pattern| {
+                builder.include(*pattern)
+            });
+        exclude
+            .iter()
+            .fold(builder, |builder, pattern| builder.exclude(*pattern))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn no_patterns_select_everything() {
+        let filter = filter(&[], &[]);
+        assert!(filter.matches_name("a"));
+        assert!(filter.matches_name("a/b/c.txt"));
+    }
+
+    #[test]
+    fn stars_stop_at_slashes() {
+        let filter = filter(&["*.json", "docs/**/*.md"], &[]);
+        assert!(filter.matches_name("a.json"));
+        assert!(!filter.matches_name("dir/a.json"));
+        assert!(filter.matches_name("docs/a.md"));
+        assert!(filter.matches_name("docs/a/b/c.md"));
+        assert!(!filter.matches_name("src/a.md"));
+    }
+
+    #[test]
+    fn directories_select_everything_below_them() {
+        let filter = filter(&["./src/"], &["src/generated"]);
+        assert!(filter.matches_name("src/"));
+        assert!(filter.matches_name("src/lib.rs"));
+        assert!(!filter.matches_name("src/generated/out.rs"));
+        assert!(!filter.matches_name("tests/it.rs"));
+    }
+
+    #[test]
+    fn exclude_patterns_win() {
+        let filter = filter(&["**/*.rs"], &["**/test_*"]);
+        assert!(filter.matches_name("src/main.rs"));
+        assert!(!filter.matches_name("src/test_main.rs"));
+    }
+
+    #[test]
+    fn case_is_ignored_on_request() {
+        let sensitive = filter(&["*.TXT"], &[]);
+        assert!(!sensitive.matches_name("a.txt"));
+        let insensitive = EntryFilter::builder()
+            .include("*.TXT")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert!(insensitive.matches_name("a.txt"));
+    }
+
+    #[test]
+    fn unsafe_names_are_matched_as_stored() {
+        let mut entry = file_entry("../evil.sh", 1, Some(1));
+        entry.enclosed_name = None;
+        assert!(filter(&["../*.sh"], &[]).matches(&entry));
+        assert!(!filter(&["*.sh"], &[]).matches(&entry));
+    }
+
+    #[test]
+    fn invalid_patterns_are_reported() {
+        match EntryFilter::builder().exclude("a[").build() {
+            Err(ExtractError::InvalidPattern { pattern, .. }) => assert_eq!(pattern, "a["),
+            result => panic!("expected an invalid pattern, got {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ExtractError;
+use crate::extractor::{ExtractedFile, FileKind, Outcome};
+
+/// Name of the journal inside the output directory.
+const JOURNAL_NAME: &str = ".rust_decompress-journal";
+
+/// One completed entry, as a line of JSON.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Record {
+    name: String,
+    size: u64,
+    crc32: Option<u32>,
+}
+
+impl Record {
+    fn new(extracted_file: &ExtractedFile) -> Self {
+        Self {
+            name: extracted_file.name.clone(),
+            size: match extracted_file.kind {
+                FileKind::File { size } => size,
+                _ => 0,
+            },
+            crc32: extracted_file.crc32,
+        }
+    }
+}
+
+/// Records the entries an extraction has completed, so that a later run can
+/// skip them if it was interrupted. The journal is removed once every entry
+/// has been written, or when the run failed in a way resuming would not get
+/// past.
+pub(crate) struct Journal {
+    path: PathBuf,
+    /// Created with the first record, so that a run writing nothing leaves no
+    /// journal behind.
+    file: Mutex<Option<File>>,
+    /// Records left by an earlier run, when resuming.
+    completed: HashMap<String, Record>,
+}
+
+impl Journal {
+    /// Starts a journal in `output_dir`. When resuming, the records of the
+    /// earlier run are read and written out again; otherwise they are
+    /// discarded.
+    pub fn open(output_dir: &Path, resume: bool) -> Result<Self, ExtractError> {
+        fs::create_dir_all(output_dir).map_err(|err| ExtractError::output_dir(output_dir, err))?;
+        let path = output_dir.join(JOURNAL_NAME);
+        let completed = if resume {
+            read_records(&path)?
+        } else {
+            HashMap::new()
+        };
+        let file = if completed.is_empty() {
+            match fs::remove_file(&path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                _ => None,
+            }
+        } else {
+            // Rewriting the records drops a last line cut short, which new
+            // records would otherwise be appended to.
+            let mut file =
+                File::create(&path).map_err(|err| ExtractError::output_dir(output_dir, err))?;
+            for record in completed.values() {
+                file.write_all(&line(record)?)?;
+            }
+            Some(file)
+        };
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+            completed,
+        })
+    }
+
+    /// Marks the entries an earlier run already wrote as
+    /// [`Outcome::Resumed`]. An entry counts only if the archive still
+    /// describes it the same way and what is on disk still matches.
+    pub fn resume(&self, extracted_files: &mut [ExtractedFile]) {
+        for extracted_file in extracted_files {
+            if self.is_complete(extracted_file) {
+                extracted_file.outcome = Outcome::Resumed;
+            }
+        }
+    }
+
+    pub fn is_complete(&self, extracted_file: &ExtractedFile) -> bool {
+        if self.completed.get(&extracted_file.name) != Some(&Record::new(extracted_file)) {
+            return false;
+        }
+        let metadata = match fs::symlink_metadata(&extracted_file.path) {
+            Ok(metadata) => metadata,
+            Err(_) => return false,
+        };
+        match extracted_file.kind {
+            FileKind::File { size } => metadata.is_file() && metadata.len() == size,
+            FileKind::Symlink => metadata.file_type().is_symlink(),
+            FileKind::Hardlink => metadata.is_file(),
+            FileKind::Directory => false,
+        }
+    }
+
+    /// Records an entry once it has been written in full. Directories are
+    /// cheap to create again and are left out.
+    pub fn complete(&self, extracted_file: &ExtractedFile) -> Result<(), ExtractError> {
+        if extracted_file.kind == FileKind::Directory {
+            return Ok(());
+        }
+        let line = line(&Record::new(extracted_file))?;
+        let mut file = self.file.lock().unwrap();
+        let file = match &mut *file {
+            Some(file) => file,
+            None => file.insert(File::create(&self.path)?),
+        };
+        // A single write per record, so that an interrupted run leaves at most
+        // the last line incomplete.
+        file.write_all(&line)?;
+        Ok(())
+    }
+
+    /// Removes the journal unless an entry failed in a way a resume could get
+    /// past, and keeps it for that resume otherwise.
+    pub fn finish(self, extracted_files: &[ExtractedFile]) -> Result<(), ExtractError> {
+        if !extracted_files
+            .iter()
+            .filter(|extracted_file| extracted_file.outcome == Outcome::Failed)
+            .filter_map(|extracted_file| extracted_file.error.as_ref())
+            .any(worth_resuming)
+        {
+            self.remove()?;
+        }
+        Ok(())
+    }
+
+    /// Ends a run that stopped at `err`, keeping the journal only if a resume
+    /// could get past it.
+    pub fn abort(self, err: &ExtractError) {
+        if !worth_resuming(err) {
+            let _ = self.remove();
+        }
+    }
+
+    fn remove(self) -> io::Result<()> {
+        match self.file.into_inner().unwrap() {
+            Some(file) => {
+                drop(file);
+                fs::remove_file(&self.path)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Whether a run that failed with `err` could get further when resumed: it
+/// was cancelled or could not read or write, which may not happen again.
+/// Limits, unsafe entries and corrupt data stop it in the same place every
+/// time.
+fn worth_resuming(err: &ExtractError) -> bool {
+    match err.root_cause() {
+        ExtractError::Cancelled | ExtractError::OutputDir { .. } => true,
+        ExtractError::IoError(err) => err.kind() != io::ErrorKind::InvalidData,
+        _ => false,
+    }
+}
+
+/// Whether an entry extracted to `enclosed_name` would land on the journal.
+pub(crate) fn is_journal(enclosed_name: &Path) -> bool {
+    let mut names = Vec::new();
+    for component in enclosed_name.components() {
+        match component {
+            Component::Normal(name) => names.push(name),
+            Component::ParentDir => {
+                names.pop();
+            }
+            _ => {}
+        }
+    }
+    names == [OsStr::new(JOURNAL_NAME)]
+}
+
+fn line(record: &Record) -> io::Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// Reads the records of an earlier run, ignoring lines that cannot be
+/// parsed, such as one cut short when the run was killed.
+fn read_records(path: &Path) -> Result<HashMap<String, Record>, ExtractError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut records = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        if let Ok(record) = serde_json::from_str::<Record>(&line?) {
+            records.insert(record.name.clone(), record);
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::LimitKind;
+    use crate::test_util::scratch_dir;
+
+    fn extracted_file(output_dir: &Path, name: &str) -> ExtractedFile {
+        ExtractedFile {
+            name: name.to_string(),
+            path: output_dir.join(name),
+            kind: FileKind::File { size: 4 },
+            link_target: None,
+            index: 0,
+            modified: None,
+            unix_mode: None,
+            compressed_size: None,
+            crc32: Some(0),
+            outcome: Outcome::Created,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn nothing_is_written_before_the_first_record() {
+        let dir = scratch_dir("journal-empty");
+        let journal = Journal::open(&dir, false).unwrap();
+        assert!(!dir.join(JOURNAL_NAME).exists());
+        journal.complete(&extracted_file(&dir, "a")).unwrap();
+        assert!(dir.join(JOURNAL_NAME).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn limit_abort_removes_the_journal() {
+        let dir = scratch_dir("journal-limit");
+        let journal = Journal::open(&dir, false).unwrap();
+        journal.complete(&extracted_file(&dir, "a")).unwrap();
+        journal.abort(&ExtractError::LimitExceeded {
+            kind: LimitKind::TotalSize,
+            limit: 1,
+            actual: 4,
+        });
+        assert!(!dir.join(JOURNAL_NAME).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cancelled_run_keeps_the_journal() {
+        let dir = scratch_dir("journal-cancel");
+        let journal = Journal::open(&dir, false).unwrap();
+        journal.complete(&extracted_file(&dir, "a")).unwrap();
+        journal.abort(&ExtractError::Cancelled);
+        let resumed = Journal::open(&dir, true).unwrap();
+        assert!(resumed.completed.contains_key("a"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn entries_landing_on_the_journal_are_recognised() {
+        assert!(is_journal(Path::new(JOURNAL_NAME)));
+        assert!(is_journal(&Path::new(".").join(JOURNAL_NAME)));
+        assert!(is_journal(&Path::new("a/..").join(JOURNAL_NAME)));
+        assert!(!is_journal(&Path::new("a").join(JOURNAL_NAME)));
+    }
+}
@@ -0,0 +1,48 @@
+//! Extraction of zip and tar archives, and creation and editing of zip
+//! archives, usable both from the `rust_decompress` binary and as a library.
+//!
+//! ```no_run
+//! use rust_decompress::ZipExtractor;
+//!
+//! let mut extractor = ZipExtractor::builder("my_archive.zip")
+//!     .output_dir("out")
+//!     .build()?;
+//! let files = extractor.extract()?;
+//! println!("extracted {} entries", files.len());
+//! # Ok::<(), rust_decompress::ExtractError>(())
+//! ```
+
+mod archive;
+mod attributes;
+mod cancel;
+mod compression;
+mod crc;
+mod creator;
+mod editor;
+mod error;
+mod extractor;
+mod filter;
+mod journal;
+mod limits;
+mod overwrite;
+mod progress;
+mod security;
+mod splice;
+mod staging;
+#[cfg(test)]
+mod test_util;
+
+pub use archive::{Encryption, Entry, Format};
+pub use cancel::CancellationToken;
+pub use compression::CompressionMethod;
+pub use creator::{AddedEntry, ZipCreator, ZipCreatorBuilder};
+pub use editor::{Edits, ZipEditor, ZipEditorBuilder};
+pub use error::ExtractError;
+pub use extractor::{
+    ExtractedFile, FileKind, Input, Outcome, Progress, TestedEntry, ZipExtractor,
+    ZipExtractorBuilder,
+};
+pub use filter::{EntryFilter, EntryFilterBuilder};
+pub use limits::{LimitKind, Limits};
+pub use overwrite::OverwritePolicy;
+pub use security::{FindingKind, SecurityFinding, SecurityMode};
@@ -0,0 +1,463 @@
+use std::fmt;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::archive::Entry;
+use crate::error::ExtractError;
+
+/// Resource limits that guard against decompression bombs. Every limit is
+/// off by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Limits {
+    /// Maximum number of entries to extract.
+    pub max_entries: Option<u64>,
+    /// Maximum uncompressed size of all entries together, in bytes.
+    pub max_total_size: Option<u64>,
+    /// Maximum uncompressed size of a single entry, in bytes.
+    pub max_entry_size: Option<u64>,
+    /// Maximum ratio of uncompressed to compressed size. Applies per entry for
+    /// zip, against the compressed bytes read so far for streamed entries
+    /// that only declare their size after the data, and to the whole archive
+    /// for tar, which is not checked when the archive is read from a stream of
+    /// unknown length.
+    pub max_ratio: Option<u64>,
+}
+
+/// The limit that was exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    Entries,
+    TotalSize,
+    EntrySize,
+    Ratio,
+}
+
+impl fmt::Display for LimitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitKind::Entries => write!(f, "entry count"),
+            LimitKind::TotalSize => write!(f, "total uncompressed size"),
+            LimitKind::EntrySize => write!(f, "entry size"),
+            LimitKind::Ratio => write!(f, "compression ratio"),
+        }
+    }
+}
+
+fn exceeded(kind: LimitKind, limit: u64, actual: u64) -> ExtractError {
+    ExtractError::LimitExceeded {
+        kind,
+        limit,
+        actual,
+    }
+}
+
+fn ratio(size: u64, compressed_size: u64) -> u64 {
+    size / compressed_size.max(1)
+}
+
+impl Limits {
+    /// Checks the sizes the archive declares before anything is written.
+    pub(crate) fn check_entries(
+        &self,
+        entries: &[&Entry],
+        archive_len: u64,
+    ) -> Result<(), ExtractError> {
+        if let Some(max) = self.max_entries {
+            if entries.len() as u64 > max {
+                return Err(exceeded(LimitKind::Entries, max, entries.len() as u64));
+            }
+        }
+        // Declared sizes can be anything, so a sum too large to count
+        // exceeds any limit.
+        let total = entries
+            .iter()
+            .fold(0u64, |total, entry| total.saturating_add(entry.size));
+        if let Some(max) = self.max_total_size {
+            if total > max {
+                return Err(exceeded(LimitKind::TotalSize, max, total));
+            }
+        }
+        for entry in entries {
+            self.check_entry(entry)?;
+        }
+        if let Some(max) = self.max_ratio {
+            if entries.iter().all(|entry| entry.compressed_size.is_none())
+                && ratio(total, archive_len) > max
+            {
+                return Err(exceeded(LimitKind::Ratio, max, ratio(total, archive_len)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks an entry of a stream, the `count`th to be extracted, as it
+    /// arrives. The total size is only enforced while decompressing.
+    pub(crate) fn check_streamed_entry(
+        &self,
+        entry: &Entry,
+        count: u64,
+    ) -> Result<(), ExtractError> {
+        if let Some(max) = self.max_entries {
+            if count > max {
+                return Err(exceeded(LimitKind::Entries, max, count));
+            }
+        }
+        self.check_entry(entry)
+    }
+
+    fn check_entry(&self, entry: &Entry) -> Result<(), ExtractError> {
+        if let Some(max) = self.max_entry_size {
+            if entry.size > max {
+                return Err(exceeded(LimitKind::EntrySize, max, entry.size));
+            }
+        }
+        if let (Some(max), Some(compressed_size)) = (self.max_ratio, entry.compressed_size) {
+            if ratio(entry.size, compressed_size) > max {
+                return Err(exceeded(
+                    LimitKind::Ratio,
+                    max,
+                    ratio(entry.size, compressed_size),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Running totals of the bytes actually decompressed, which is what the
+/// limits are enforced against while streaming. Shared by all threads of a
+/// parallel extraction.
+#[derive(Debug)]
+pub(crate) struct Usage {
+    limits: Limits,
+    /// `None` for a stream of unknown length.
+    archive_len: Option<u64>,
+    /// Stored bytes read so far of the entry being streamed, for entries
+    /// that do not declare their compressed size up front.
+    compressed_read: Option<Arc<AtomicU64>>,
+    total: AtomicU64,
+}
+
+impl Usage {
+    pub fn new(
+        limits: Limits,
+        archive_len: Option<u64>,
+        compressed_read: Option<Arc<AtomicU64>>,
+    ) -> Self {
+        Self {
+            limits,
+            archive_len,
+            compressed_read,
+            total: AtomicU64::new(0),
+        }
+    }
+
+    /// Wraps the reader of one entry so it fails as soon as a limit is hit.
+    pub fn reader<'a, R: Read>(
+        &'a self,
+        inner: R,
+        compressed_size: Option<u64>,
+    ) -> LimitedReader<'a, R> {
+        LimitedReader {
+            inner,
+            usage: self,
+            compressed_size,
+            read: 0,
+            exceeded: None,
+        }
+    }
+}
+
+pub(crate) struct LimitedReader<'a, R> {
+    inner: R,
+    usage: &'a Usage,
+    compressed_size: Option<u64>,
+    read: u64,
+    exceeded: Option<(LimitKind, u64, u64)>,
+}
+
+impl<R> LimitedReader<'_, R> {
+    /// The limit that stopped the reader, if any.
+    pub fn exceeded(&self) -> Option<ExtractError> {
+        self.exceeded
+            .map(|(kind, limit, actual)| exceeded(kind, limit, actual))
+    }
+
+    fn check(&self, total: u64) -> Option<(LimitKind, u64, u64)> {
+        let limits = &self.usage.limits;
+        if let Some(max) = limits.max_entry_size {
+            if self.read > max {
+                return Some((LimitKind::EntrySize, max, self.read));
+            }
+        }
+        if let Some(max) = limits.max_total_size {
+            if total > max {
+                return Some((LimitKind::TotalSize, max, total));
+            }
+        }
+        if let Some(max) = limits.max_ratio {
+            let compressed_size = self.compressed_size.or_else(|| {
+                let compressed_read = self.usage.compressed_read.as_ref()?;
+                Some(compressed_read.load(Ordering::Relaxed))
+            });
+            let actual = match (compressed_size, self.usage.archive_len) {
+                (Some(compressed_size), _) => Some(ratio(self.read, compressed_size)),
+                (None, Some(archive_len)) => Some(ratio(total, archive_len)),
+                (None, None) => None,
+            };
+            if let Some(actual) = actual.filter(|&actual| actual > max) {
+                return Some((LimitKind::Ratio, max, actual));
+            }
+        }
+        None
+    }
+}
+
+impl<R: Read> Read for LimitedReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.read += count as u64;
+        let total = self.usage.total.fetch_add(count as u64, Ordering::Relaxed) + count as u64;
+        if let Some(exceeded) = self.check(total) {
+            self.exceeded = Some(exceeded);
+            return Err(io::Error::other("resource limit exceeded"));
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::test_util::{file_entry, scratch_dir, zip64_archive, zip_with_descriptor};
+    use crate::{Input, ZipExtractor};
+
+    fn refused(result: Result<(), ExtractError>) -> Option<(LimitKind, u64, u64)> {
+        match result {
+            Ok(()) => None,
+            Err(ExtractError::LimitExceeded {
+                kind,
+                limit,
+                actual,
+            }) => Some((kind, limit, actual)),
+            Err(err) => panic!("expected a limit to be exceeded, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn no_limits_accept_anything() {
+        let entries = [file_entry("a", u64::MAX, Some(1)), file_entry("b", 1, None)];
+        let entries: Vec<_> = entries.iter().collect();
+        assert_eq!(refused(Limits::default().check_entries(&entries, 0)), None);
+    }
+
+    #[test]
+    fn declared_sizes_are_checked_against_each_limit() {
+        let entries = [
+            file_entry("a", 600, Some(10)),
+            file_entry("b", 500, Some(100)),
+        ];
+        let entries: Vec<_> = entries.iter().collect();
+        let check = |limits: Limits| refused(limits.check_entries(&entries, 200));
+
+        let max_entries = |max| Limits {
+            max_entries: Some(max),
+            ..Limits::default()
+        };
+        assert_eq!(check(max_entries(2)), None);
+        assert_eq!(check(max_entries(1)), Some((LimitKind::Entries, 1, 2)));
+
+        let max_total_size = |max| Limits {
+            max_total_size: Some(max),
+            ..Limits::default()
+        };
+        assert_eq!(check(max_total_size(1100)), None);
+        assert_eq!(
+            check(max_total_size(1099)),
+            Some((LimitKind::TotalSize, 1099, 1100))
+        );
+
+        let max_entry_size = |max| Limits {
+            max_entry_size: Some(max),
+            ..Limits::default()
+        };
+        assert_eq!(check(max_entry_size(600)), None);
+        assert_eq!(
+            check(max_entry_size(599)),
+            Some((LimitKind::EntrySize, 599, 600))
+        );
+
+        // Zip entries are held to the ratio one by one: 600 / 10 for `a`.
+        let max_ratio = |max| Limits {
+            max_ratio: Some(max),
+            ..Limits::default()
+        };
+        assert_eq!(check(max_ratio(60)), None);
+        assert_eq!(check(max_ratio(59)), Some((LimitKind::Ratio, 59, 60)));
+    }
+
+    #[test]
+    fn tar_ratio_is_taken_over_the_whole_archive() {
+        let entries = [file_entry("a", 600, None), file_entry("b", 500, None)];
+        let entries: Vec<_> = entries.iter().collect();
+        let limits = |max| Limits {
+            max_ratio: Some(max),
+            ..Limits::default()
+        };
+        assert_eq!(refused(limits(11).check_entries(&entries, 100)), None);
+        assert_eq!(
+            refused(limits(10).check_entries(&entries, 100)),
+            Some((LimitKind::Ratio, 10, 11))
+        );
+        // An empty archive file cannot be divided by.
+        assert_eq!(
+            refused(limits(10).check_entries(&entries, 0)),
+            Some((LimitKind::Ratio, 10, 1100))
+        );
+    }
+
+    #[test]
+    fn streamed_entries_are_counted_as_they_arrive() {
+        let limits = Limits {
+            max_entries: Some(2),
+            max_entry_size: Some(100),
+            ..Limits::default()
+        };
+        let small = file_entry("a", 100, None);
+        assert_eq!(refused(limits.check_streamed_entry(&small, 2)), None);
+        assert_eq!(
+            refused(limits.check_streamed_entry(&small, 3)),
+            Some((LimitKind::Entries, 2, 3))
+        );
+        assert_eq!(
+            refused(limits.check_streamed_entry(&file_entry("b", 101, None), 1)),
+            Some((LimitKind::EntrySize, 100, 101))
+        );
+    }
+
+    #[test]
+    fn readers_stop_at_the_limit_they_exceed() {
+        let usage = Usage::new(
+            Limits {
+                max_total_size: Some(1000),
+                max_entry_size: Some(600),
+                ..Limits::default()
+            },
+            None,
+            None,
+        );
+        let mut first = usage.reader(Cursor::new(vec![0; 600]), None);
+        io::copy(&mut first, &mut io::sink()).unwrap();
+        assert!(first.exceeded().is_none());
+
+        let mut second = usage.reader(Cursor::new(vec![0; 600]), None);
+        assert!(io::copy(&mut second, &mut io::sink()).is_err());
+        match second.exceeded() {
+            Some(ExtractError::LimitExceeded {
+                kind: LimitKind::TotalSize,
+                limit: 1000,
+                actual: 1200,
+            }) => {}
+            exceeded => panic!("expected the total size to be exceeded, got {:?}", exceeded),
+        }
+
+        let mut large = usage.reader(Cursor::new(vec![0; 601]), None);
+        assert!(io::copy(&mut large, &mut io::sink()).is_err());
+        assert!(matches!(
+            large.exceeded(),
+            Some(ExtractError::LimitExceeded {
+                kind: LimitKind::EntrySize,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn readers_hold_entries_to_the_ratio() {
+        let limits = Limits {
+            max_ratio: Some(10),
+            ..Limits::default()
+        };
+        let ratio_of = |usage: &Usage, compressed_size| {
+            let mut reader = usage.reader(Cursor::new(vec![0; 1000]), compressed_size);
+            let _ = io::copy(&mut reader, &mut io::sink());
+            match reader.exceeded() {
+                None => None,
+                Some(ExtractError::LimitExceeded { actual, .. }) => Some(actual),
+                Some(err) => panic!("expected the ratio to be exceeded, got {:?}", err),
+            }
+        };
+
+        // Against the declared compressed size, then the archive length.
+        assert_eq!(ratio_of(&Usage::new(limits, None, None), Some(100)), None);
+        assert_eq!(
+            ratio_of(&Usage::new(limits, None, None), Some(90)),
+            Some(11)
+        );
+        assert_eq!(
+            ratio_of(&Usage::new(limits, Some(90), None), None),
+            Some(11)
+        );
+        // A stream of unknown length leaves nothing to compare against.
+        assert_eq!(ratio_of(&Usage::new(limits, None, None), None), None);
+        // Streamed entries compare against what was read of them so far.
+        let compressed_read = Arc::new(AtomicU64::new(90));
+        let usage = Usage::new(limits, None, Some(compressed_read));
+        assert_eq!(ratio_of(&usage, None), Some(11));
+    }
+
+    #[test]
+    fn declared_sizes_too_large_to_add_exceed_the_total() {
+        let dir = scratch_dir("limits-overflow");
+        let archive = dir.join("big.zip");
+        fs::write(&archive, zip64_archive(&[1 << 63, 1 << 63])).unwrap();
+        let result = ZipExtractor::builder(archive.as_path())
+            .output_dir(dir.join("out"))
+            .limits(Limits {
+                max_total_size: Some(100),
+                ..Limits::default()
+            })
+            .build()
+            .unwrap()
+            .extract();
+        match result.as_ref().map_err(ExtractError::root_cause) {
+            Err(ExtractError::LimitExceeded {
+                kind: LimitKind::TotalSize,
+                limit: 100,
+                actual: u64::MAX,
+            }) => {}
+            result => panic!("expected the total size to be refused, got {:?}", result),
+        }
+        assert!(!dir.join("out").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn streamed_entry_with_descriptor_is_held_to_the_ratio() {
+        let dir = scratch_dir("limits-streamed-ratio");
+        let bomb = zip_with_descriptor("bomb", &vec![0; 4 << 20]);
+        let result = ZipExtractor::builder(Input::Stream(Box::new(Cursor::new(bomb))))
+            .output_dir(&dir)
+            .limits(Limits {
+                max_ratio: Some(100),
+                ..Limits::default()
+            })
+            .build()
+            .unwrap()
+            .extract();
+        match result.as_ref().map_err(ExtractError::root_cause) {
+            Err(ExtractError::LimitExceeded {
+                kind: LimitKind::Ratio,
+                limit: 100,
+                ..
+            }) => {}
+            result => panic!("expected the ratio to be refused, got {:?}", result),
+        }
+        assert!(!dir.join("bomb").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,171 +1,181 @@
-use std::fs::{self, File};
+mod commands;
+
 use std::io;
-use std::path::PathBuf;
+use std::process;
 
-use indicatif::{ProgressBar, ProgressStyle};
+use rust_decompress::ExtractError;
+use structopt::clap::{self, AppSettings};
 use structopt::StructOpt;
 
+use crate::commands::cat::CatOpt;
+use crate::commands::create::CreateOpt;
+use crate::commands::edit::{AddOpt, DeleteOpt};
+use crate::commands::extract::ExtractOpt;
+use crate::commands::list::ListOpt;
+use crate::commands::test::TestOpt;
+
+/// Some entries failed while the others were handled, e.g. when testing.
+const EXIT_PARTIAL: i32 = 1;
+/// The arguments are wrong, the input is not an archive we can read, lacks
+/// a requested entry or needs a password that was not given, or a file is in
+/// the way of the output.
+const EXIT_BAD_INPUT: i32 = 2;
+/// The archive is damaged: a CRC-32 or size does not match, or the data
+/// cannot be decoded, e.g. for an unsupported compression method.
+const EXIT_CORRUPT: i32 = 3;
+/// Reading or writing a file failed.
+const EXIT_IO: i32 = 4;
+/// Entries were refused as unsafe in strict mode or broke a resource limit.
+const EXIT_SECURITY: i32 = 5;
+/// Interrupted by SIGINT or SIGTERM, as shells report a Ctrl-C.
+pub const EXIT_CANCELLED: i32 = 130;
+
+const EXIT_CODES: &str = "EXIT CODES:
+    0    Success
+    1    Partial success: some entries failed
+    2    Bad input: wrong arguments, unreadable archive format, missing or existing entry, missing password, existing output file
+    3    Corrupt archive: CRC-32 or size mismatch, undecodable data, unsupported method
+    4    I/O failure reading or writing files
+    5    Security violation: unsafe entries in strict mode, resource limit exceeded
+    130  Cancelled by SIGINT or SIGTERM";
+
 #[derive(Debug, StructOpt)]
-#[structopt(name = "unzip", about = "Extracts files from a zip archive")]
+#[structopt(
+    name = "unzip",
+    about = "Extracts files from a zip or tar archive, or creates and edits zip archives",
+    after_help = EXIT_CODES
+)]
+// Lets archive names that resemble a subcommand reach the `input` argument.
+#[structopt(setting = AppSettings::AllowExternalSubcommands)]
 struct Opt {
-    /// The zip file to extract
-    #[structopt(parse(from_os_str))]
-    input: PathBuf,
+    #[structopt(subcommand)]
+    command: Option<Command>,
 
-    /// The directory to extract the files to
-    #[structopt(parse(from_os_str))]
-    output_dir: Option<PathBuf>,
-
-    /// Show a progress bar
-    #[structopt(short, long)]
-    progress: bool,
+    #[structopt(flatten)]
+    extract: ExtractOpt,
 }
 
-#[derive(Debug)]
-enum ExtractError {
-    IoError(io::Error),
-    ZipError(zip::result::ZipError),
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Lists the entries of an archive without extracting them
+    List(ListOpt),
+    /// Verifies the CRC-32 and size of every entry without writing files
+    Test(TestOpt),
+    /// Writes the data of entries to standard output without extracting them
+    Cat(CatOpt),
+    /// Creates a zip archive from files and directories
+    Create(CreateOpt),
+    /// Adds files to a zip archive, refusing names it already has
+    Add(AddOpt),
+    /// Adds files to a zip archive, replacing entries of the same name
+    Update(AddOpt),
+    /// Removes entries from a zip archive
+    Delete(DeleteOpt),
 }
 
-impl From<io::Error> for ExtractError {
-    fn from(err: io::Error) -> Self {
-        ExtractError::IoError(err)
+/// Returns whether the command succeeded for every entry.
+fn run(opt: Opt) -> Result<bool, ExtractError> {
+    match opt.command {
+        Some(Command::List(list)) => commands::list::run(list).map(|()| true),
+        Some(Command::Test(test)) => commands::test::run(test),
+        Some(Command::Cat(cat)) => commands::cat::run(cat).map(|()| true),
+        Some(Command::Create(create)) => commands::create::run(create).map(|()| true),
+        Some(Command::Add(add)) => commands::edit::run_add(add, false).map(|()| true),
+        Some(Command::Update(update)) => commands::edit::run_add(update, true).map(|()| true),
+        Some(Command::Delete(delete)) => commands::edit::run_delete(delete).map(|()| true),
+        None => commands::extract::run(opt.extract),
     }
 }
 
-impl From<zip::result::ZipError> for ExtractError {
-    fn from(err: zip::result::ZipError) -> Self {
-        ExtractError::ZipError(err)
+/// Maps an error to one of the documented exit codes.
+fn exit_code(err: &ExtractError) -> i32 {
+    // An archive that cannot be opened at all is bad input, not an IO failure.
+    if let ExtractError::InArchive { source, .. } = err {
+        if let ExtractError::IoError(err) = source.as_ref() {
+            if err.kind() == io::ErrorKind::NotFound {
+                return EXIT_BAD_INPUT;
+            }
+        }
+    }
+    match err.root_cause() {
+        ExtractError::UnrecognizedFormat { .. }
+        | ExtractError::EntryNotFound(_)
+        | ExtractError::EntryExists(_)
+        | ExtractError::IsDirectory(_)
+        | ExtractError::InvalidPattern { .. }
+        | ExtractError::InvalidLevel { .. }
+        | ExtractError::PasswordRequired
+        | ExtractError::WrongPassword
+        | ExtractError::AlreadyExists(_) => EXIT_BAD_INPUT,
+        ExtractError::ZipError(_)
+        | ExtractError::CrcMismatch { .. }
+        | ExtractError::SizeMismatch { .. }
+        | ExtractError::UnsupportedEncryption
+        | ExtractError::UnsupportedMethod { .. } => EXIT_CORRUPT,
+        ExtractError::IoError(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+            ) =>
+        {
+            EXIT_CORRUPT
+        }
+        ExtractError::UnsafeEntries(_) | ExtractError::LimitExceeded { .. } => EXIT_SECURITY,
+        ExtractError::Cancelled => EXIT_CANCELLED,
+        _ => EXIT_IO,
     }
 }
 
-#[derive(Debug)]
-enum FileKind {
-    Directory,
-    File { size: u64 },
-}
-
-#[derive(Debug)]
-struct ExtractedFile {
-    path: PathBuf,
-    kind: FileKind,
-    index: usize,
-}
-
-struct ZipExtractor<'a> {
-    archive: zip::ZipArchive<&'a File>,
-    output_dir: PathBuf,
-    progress_bar: Option<ProgressBar>,
-}
-
-impl<'a> ZipExtractor<'a> {
-    fn new(zip_file: &'a File, output_dir: PathBuf, progress: bool) -> Result<Self, ExtractError> {
-        let archive = zip::ZipArchive::new(zip_file)?;
-        let progress_bar = if progress {
-            let pb = ProgressBar::new(archive.len() as u64);
-            pb.set_style(
-                ProgressStyle::default_bar()
-                    .template("{msg} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
-                    .unwrap(),
-            );
-            pb.set_message("Extracting files...");
-            Some(pb)
-        } else {
-            None
-        };
-        Ok(Self {
-            archive,
-            output_dir,
-            progress_bar,
-        })
+/// Reports a command line error, exiting with [`EXIT_BAD_INPUT`] rather than
+/// the code clap uses. Help and version requests exit successfully.
+pub fn usage_error(err: clap::Error) -> ! {
+    if !err.use_stderr() {
+        err.exit();
     }
+    eprintln!("{}", err.message);
+    process::exit(EXIT_BAD_INPUT);
+}
 
-    fn extract(&mut self) -> Result<Vec<ExtractedFile>, ExtractError> {
-        let extracted_files = self.get_extracted_files()?;
-        self.write_extracted_files(&extracted_files)?;
-        self.finish_progress_bar(&extracted_files)?;
-        Ok(extracted_files)
+fn main() {
+    let opt = Opt::from_args_safe().unwrap_or_else(|err| usage_error(err));
+    match run(opt) {
+        Ok(true) => {}
+        Ok(false) => process::exit(EXIT_PARTIAL),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            process::exit(exit_code(&err));
+        }
     }
+}
 
-    fn get_extracted_files(&mut self) -> Result<Vec<ExtractedFile>, ExtractError> {
-        let extracted_files = (0..self.archive.len())
-            .filter_map(|i| {
-                let file = self.archive.by_index(i).ok()?;
-                let outpath = match file.enclosed_name() {
-                    Some(path) => self.output_dir.join(path),
-                    None => return None,
-                };
-
-                let kind = if (*file.name()).ends_with('/') {
-                    FileKind::Directory
-                } else {
-                    FileKind::File { size: file.size() }
-                };
-
-                Some(ExtractedFile {
-                    path: outpath,
-                    kind,
-                    index: i,
-                })
-            })
-            .collect::<Vec<_>>();
-
-        Ok(extracted_files)
-    }
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
 
-    fn write_extracted_files(
-        &mut self,
-        extracted_files: &Vec<ExtractedFile>,
-    ) -> Result<(), ExtractError> {
-        for extracted_file in extracted_files {
-            match extracted_file.kind {
-                FileKind::Directory => {
-                    let dir_path = &extracted_file.path;
-                    if !dir_path.exists() {
-                        fs::create_dir_all(dir_path)?;
-                    }
-                }
-                FileKind::File { .. } => {
-                    let outpath = &extracted_file.path;
-                    let mut outfile = fs::File::create(outpath)?;
-                    let mut reader = self.archive.by_index(extracted_file.index)?;
-                    io::copy(&mut reader, &mut outfile)?;
-                }
-            }
+    use super::*;
 
-            if let Some(pb) = &mut self.progress_bar {
-                pb.inc(1);
-            }
+    fn in_archive(err: ExtractError) -> ExtractError {
+        ExtractError::InArchive {
+            archive: PathBuf::from("archive.zip"),
+            source: Box::new(err),
         }
-
-        Ok(())
     }
 
-    fn finish_progress_bar(
-        &mut self,
-        extracted_files: &Vec<ExtractedFile>,
-    ) -> Result<(), ExtractError> {
-        if let Some(pb) = &mut self.progress_bar {
-            pb.finish_with_message(format!("Extracted {} files", extracted_files.len()));
-        }
-
-        Ok(())
+    #[test]
+    fn existing_output_is_bad_input() {
+        let err = ExtractError::AlreadyExists(PathBuf::from("out/a"));
+        assert_eq!(exit_code(&in_archive(err)), EXIT_BAD_INPUT);
     }
-}
 
-fn extract(opt: Opt) -> Result<(), ExtractError> {
-    let output_dir = opt
-        .output_dir
-        .unwrap_or_else(|| PathBuf::from(".").join(opt.input.file_stem().unwrap()));
-    let zip_file = File::open(opt.input)?;
-    let mut extractor = ZipExtractor::new(&zip_file, output_dir, opt.progress)?;
-    extractor.extract()?;
-    Ok(())
-}
+    #[test]
+    fn undecodable_data_is_corrupt() {
+        let err = io::Error::new(io::ErrorKind::InvalidData, "corrupt deflate stream");
+        assert_eq!(exit_code(&in_archive(err.into())), EXIT_CORRUPT);
+    }
 
-fn main() {
-    let opt = Opt::from_args();
-    if let Err(err) = extract(opt) {
-        eprintln!("Error: {:?}", err);
+    #[test]
+    fn missing_archive_is_bad_input() {
+        let err = io::Error::from(io::ErrorKind::NotFound);
+        assert_eq!(exit_code(&in_archive(err.into())), EXIT_BAD_INPUT);
     }
 }
@@ -0,0 +1,181 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use time::OffsetDateTime;
+
+use crate::error::ExtractError;
+use crate::extractor::Outcome;
+
+/// What to do when an entry would be written over an existing file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverwritePolicy {
+    /// Replace the existing file.
+    #[default]
+    Overwrite,
+    /// Keep the existing file and leave the entry out.
+    Skip,
+    /// Replace the existing file only if the entry was modified after it.
+    IfNewer,
+    /// Write the entry next to the existing file under a numbered name.
+    Rename,
+    /// Stop the extraction with [`ExtractError::AlreadyExists`].
+    Fail,
+}
+
+impl FromStr for OverwritePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "overwrite" => Ok(OverwritePolicy::Overwrite),
+            "skip" => Ok(OverwritePolicy::Skip),
+            "newer" => Ok(OverwritePolicy::IfNewer),
+            "rename" => Ok(OverwritePolicy::Rename),
+            "fail" => Ok(OverwritePolicy::Fail),
+            _ => Err(format!("unknown overwrite policy: {}", s)),
+        }
+    }
+}
+
+impl OverwritePolicy {
+    pub const VARIANTS: &'static [&'static str] = &["overwrite", "skip", "newer", "rename", "fail"];
+
+    /// Decides where a file entry goes and how that is reported.
+    pub(crate) fn resolve(
+        self,
+        path: &Path,
+        modified: Option<OffsetDateTime>,
+    ) -> Result<Outcome, ExtractError> {
+        let existing = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Outcome::Created),
+            Err(err) => return Err(err.into()),
+        };
+        match self {
+            OverwritePolicy::Overwrite => Ok(Outcome::Overwritten),
+            OverwritePolicy::Skip => Ok(Outcome::Skipped),
+            OverwritePolicy::IfNewer => {
+                let newer = match (modified, existing.modified()) {
+                    (Some(modified), Ok(existing)) => SystemTime::from(modified) > existing,
+                    _ => false,
+                };
+                Ok(if newer {
+                    Outcome::Overwritten
+                } else {
+                    Outcome::Skipped
+                })
+            }
+            OverwritePolicy::Rename => Ok(Outcome::Renamed(free_name(path))),
+            OverwritePolicy::Fail => Err(ExtractError::AlreadyExists(path.to_path_buf())),
+        }
+    }
+}
+
+/// Finds the first `name (n).ext` next to `path` that does not exist yet.
+fn free_name(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, extension)))
+        .find(|candidate| fs::symlink_metadata(candidate).is_err())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+    use crate::test_util::scratch_dir;
+
+    #[test]
+    fn missing_files_are_created_under_every_policy() {
+        let dir = scratch_dir("overwrite-missing");
+        for policy in [
+            OverwritePolicy::Overwrite,
+            OverwritePolicy::Skip,
+            OverwritePolicy::IfNewer,
+            OverwritePolicy::Rename,
+            OverwritePolicy::Fail,
+        ] {
+            let outcome = policy.resolve(&dir.join("file"), None).unwrap();
+            assert_eq!(outcome, Outcome::Created, "{:?}", policy);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn existing_files_are_handled_by_the_policy() {
+        let dir = scratch_dir("overwrite-existing");
+        let path = dir.join("file.txt");
+        fs::write(&path, "old").unwrap();
+        let resolve = |policy: OverwritePolicy| policy.resolve(&path, None);
+        assert_eq!(
+            resolve(OverwritePolicy::Overwrite).unwrap(),
+            Outcome::Overwritten
+        );
+        assert_eq!(resolve(OverwritePolicy::Skip).unwrap(), Outcome::Skipped);
+        match resolve(OverwritePolicy::Fail) {
+            Err(ExtractError::AlreadyExists(existing)) => assert_eq!(existing, path),
+            outcome => panic!("expected the file to be refused, got {:?}", outcome),
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_newer_entries_replace_existing_files() {
+        let dir = scratch_dir("overwrite-newer");
+        let path = dir.join("file");
+        fs::write(&path, "old").unwrap();
+        let existing = OffsetDateTime::from(fs::metadata(&path).unwrap().modified().unwrap());
+        let resolve = |modified| OverwritePolicy::IfNewer.resolve(&path, modified).unwrap();
+        assert_eq!(
+            resolve(Some(existing + Duration::hours(1))),
+            Outcome::Overwritten
+        );
+        assert_eq!(
+            resolve(Some(existing - Duration::hours(1))),
+            Outcome::Skipped
+        );
+        // Without a time there is nothing to show the entry is newer.
+        assert_eq!(resolve(None), Outcome::Skipped);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn renamed_entries_take_the_first_free_number() {
+        let dir = scratch_dir("overwrite-rename");
+        fs::write(dir.join("file.txt"), "").unwrap();
+        fs::write(dir.join("file (1).txt"), "").unwrap();
+        fs::write(dir.join("notes"), "").unwrap();
+        let rename = |name: &str| {
+            OverwritePolicy::Rename
+                .resolve(&dir.join(name), None)
+                .unwrap()
+        };
+        assert_eq!(
+            rename("file.txt"),
+            Outcome::Renamed(dir.join("file (2).txt"))
+        );
+        assert_eq!(rename("notes"), Outcome::Renamed(dir.join("notes (1)")));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn policies_parse_from_their_names() {
+        for name in OverwritePolicy::VARIANTS {
+            assert!(name.parse::<OverwritePolicy>().is_ok(), "{}", name);
+        }
+        assert_eq!("newer".parse(), Ok(OverwritePolicy::IfNewer));
+        assert_eq!(
+            "never".parse::<OverwritePolicy>(),
+            Err("unknown overwrite policy: never".to_string())
+        );
+    }
+}
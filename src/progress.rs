@@ -0,0 +1,51 @@
+use std::io::{self, Read};
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Creates the progress bar shown while extracting or creating an archive.
+/// Without a known total, as when reading a stream, there is nothing to fill
+/// a bar or estimate the time left against, so only the bytes are counted.
+pub(crate) fn progress_bar(sized: bool) -> ProgressBar {
+    let pb = ProgressBar::new(0);
+    let template = if sized {
+        "{wide_msg}\n[{elapsed_precise}] [{bar:40.cyan/blue}] \
+         {bytes}/{total_bytes} {binary_bytes_per_sec} ({eta})"
+    } else {
+        "{wide_msg}\n{spinner} [{elapsed_precise}] {bytes} {binary_bytes_per_sec}"
+    };
+    pb.set_style(ProgressStyle::default_bar().template(template).unwrap());
+    pb
+}
+
+/// Advances a progress bar by the number of bytes read through it.
+pub(crate) struct ProgressReader<'a, R> {
+    inner: R,
+    progress_bar: Option<&'a ProgressBar>,
+    len: u64,
+}
+
+impl<'a, R: Read> ProgressReader<'a, R> {
+    pub fn new(inner: R, progress_bar: Option<&'a ProgressBar>) -> Self {
+        Self {
+            inner,
+            progress_bar,
+            len: 0,
+        }
+    }
+
+    /// Number of bytes read so far.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.len += count as u64;
+        if let Some(pb) = self.progress_bar {
+            pb.inc(count as u64);
+        }
+        Ok(count)
+    }
+}
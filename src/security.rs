@@ -0,0 +1,184 @@
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::archive::enclosed_name;
+
+/// How entries that would be unsafe to extract are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SecurityMode {
+    /// Refuse the whole archive before anything is written.
+    Strict,
+    /// Leave the offending entries out and report them as findings.
+    #[default]
+    Lenient,
+}
+
+/// Why an entry was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FindingKind {
+    /// The name is absolute or climbs out of the output directory with `..`.
+    PathTraversal,
+    /// A symlink or hardlink points outside the output directory.
+    LinkEscape,
+    /// The entry would be written through a symlink extracted before it.
+    WriteThroughLink,
+    /// The name is the one of the journal kept in the output directory.
+    ReservedName,
+}
+
+impl fmt::Display for FindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FindingKind::PathTraversal => write!(f, "path escapes the output directory"),
+            FindingKind::LinkEscape => write!(f, "link target escapes the output directory"),
+            FindingKind::WriteThroughLink => write!(f, "path goes through an extracted symlink"),
+            FindingKind::ReservedName => write!(f, "name is reserved for the extraction journal"),
+        }
+    }
+}
+
+/// An entry that was not extracted because doing so would be unsafe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecurityFinding {
+    pub index: usize,
+    /// The name as stored in the archive.
+    pub name: String,
+    pub kind: FindingKind,
+}
+
+impl fmt::Display for SecurityFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.kind)
+    }
+}
+
+/// Tracks the symlinks an extraction creates, relative to the output
+/// directory, so later entries cannot use them to reach outside of it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LinkGuard {
+    symlinks: Vec<PathBuf>,
+}
+
+impl LinkGuard {
+    pub fn add_symlink(&mut self, path: &Path) {
+        self.symlinks.push(path.to_path_buf());
+    }
+
+    /// Whether writing to `path` would follow one of the symlinks, either in
+    /// a parent directory or by replacing the link itself.
+    pub fn goes_through_link(&self, path: &Path) -> bool {
+        self.symlinks.iter().any(|link| path.starts_with(link))
+    }
+
+    /// Whether a symlink at `path` pointing to `target` resolves outside the
+    /// output directory. Targets that continue past another symlink are
+    /// refused as well, since where they end up depends on that link.
+    pub fn symlink_escapes(&self, path: &Path, target: &Path) -> bool {
+        let mut resolved = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut components = target.components().peekable();
+        while let Some(component) = components.next() {
+            match component {
+                Component::Prefix(_) | Component::RootDir => return true,
+                Component::ParentDir => {
+                    if !resolved.pop() {
+                        return true;
+                    }
+                }
+                Component::Normal(name) => resolved.push(name),
+                Component::CurDir => (),
+            }
+            if components.peek().is_some() && self.symlinks.contains(&resolved) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns the path a hardlink to `target` links to, relative to the
+    /// output directory, or `None` if that is not safe.
+    pub fn hardlink_target(&self, target: &Path) -> Option<PathBuf> {
+        let target = enclosed_name(target.to_str()?)?;
+        if self.goes_through_link(&target) {
+            return None;
+        }
+        Some(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_leaving_the_output_directory_are_not_enclosed() {
+        for name in ["/etc/passwd", "../x", "a/../../x", "a/\0b"] {
+            assert_eq!(enclosed_name(name), None, "{:?}", name);
+        }
+        for name in ["a/b", "a/../b", "./a", "a/b/../.."] {
+            assert_eq!(enclosed_name(name), Some(PathBuf::from(name)), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn findings_name_the_entry() {
+        let finding = SecurityFinding {
+            index: 3,
+            name: "../evil".to_string(),
+            kind: FindingKind::PathTraversal,
+        };
+        assert_eq!(
+            finding.to_string(),
+            "../evil: path escapes the output directory"
+        );
+    }
+
+    fn guard(symlinks: &[&str]) -> LinkGuard {
+        let mut guard = LinkGuard::default();
+        for link in symlinks {
+            guard.add_symlink(Path::new(link));
+        }
+        guard
+    }
+
+    #[test]
+    fn writes_through_extracted_symlinks_are_caught() {
+        let guard = guard(&["a/link"]);
+        assert!(guard.goes_through_link(Path::new("a/link")));
+        assert!(guard.goes_through_link(Path::new("a/link/file")));
+        assert!(!guard.goes_through_link(Path::new("a/linked")));
+        assert!(!guard.goes_through_link(Path::new("a")));
+    }
+
+    #[test]
+    fn symlinks_must_resolve_inside_the_output_directory() {
+        let guard = guard(&[]);
+        let escapes =
+            |path: &str, target: &str| guard.symlink_escapes(Path::new(path), Path::new(target));
+        assert!(!escapes("a/link", "file"));
+        assert!(!escapes("a/link", "../file"));
+        assert!(!escapes("a/link", "./b/../c"));
+        assert!(escapes("a/link", "../../file"));
+        assert!(escapes("link", "../file"));
+        assert!(escapes("a/link", "/etc/passwd"));
+    }
+
+    #[test]
+    fn symlink_targets_may_not_continue_past_another_symlink() {
+        let guard = guard(&["a/up"]);
+        let escapes = |target: &str| guard.symlink_escapes(Path::new("a/link"), Path::new(target));
+        assert!(escapes("up/file"));
+        // Pointing at the link itself resolves wherever it does, which was
+        // checked when it was extracted.
+        assert!(!escapes("up"));
+    }
+
+    #[test]
+    fn hardlinks_must_target_enclosed_entries() {
+        let guard = guard(&["a/link"]);
+        let target = |target: &str| guard.hardlink_target(Path::new(target));
+        assert_eq!(target("a/file"), Some(PathBuf::from("a/file")));
+        assert_eq!(target("../file"), None);
+        assert_eq!(target("/etc/passwd"), None);
+        assert_eq!(target("a/link/file"), None);
+    }
+}
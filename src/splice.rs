@@ -0,0 +1,416 @@
+//! Copies zip entries from one archive to another byte for byte, without
+//! decompressing them. The zip crate can copy raw data too, but rebuilds the
+//! headers around it and loses encryption flags, file types, extra fields
+//! and comments on the way.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::error::ExtractError;
+
+const CENTRAL_HEADER_LEN: usize = 46;
+const ZIP64_EXTRA_FIELD: u16 = 0x0001;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x0606_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR: u32 = 0x0706_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+
+/// An entry of a zip file: where its local header, data and data descriptor
+/// lie, and its central directory record.
+pub(crate) struct RawEntry {
+    pub name: String,
+    pub is_dir: bool,
+    local_start: u64,
+    local_end: u64,
+    central: Vec<u8>,
+}
+
+/// Reads where the entries of a zip file lie, and its comment.
+pub(crate) fn read_entries(file: &mut File) -> Result<(Vec<RawEntry>, Vec<u8>), ExtractError> {
+    let mut archive = zip::ZipArchive::new(&mut *file)?;
+    let comment = archive.comment().to_vec();
+    let mut located = Vec::with_capacity(archive.len());
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index)?;
+        located.push((
+            entry.name().to_string(),
+            entry.is_dir(),
+            entry.header_start(),
+            entry.data_start() + entry.compressed_size(),
+            entry.central_header_start(),
+        ));
+    }
+    drop(archive);
+
+    // A record runs up to whatever comes next, which takes in the data
+    // descriptor without having to parse it.
+    let mut boundaries = located
+        .iter()
+        .flat_map(|&(_, _, local_start, _, central_start)| [local_start, central_start])
+        .collect::<Vec<_>>();
+    boundaries.sort_unstable();
+    let mut entries = Vec::with_capacity(located.len());
+    for (name, is_dir, local_start, data_end, central_start) in located {
+        let local_end = boundaries
+            .iter()
+            .copied()
+            .find(|&boundary| boundary > local_start)
+            .filter(|&boundary| boundary >= data_end)
+            .unwrap_or(data_end);
+        entries.push(RawEntry {
+            name,
+            is_dir,
+            local_start,
+            local_end,
+            central: read_central_record(file, central_start)?,
+        });
+    }
+    Ok((entries, comment))
+}
+
+fn read_central_record(file: &mut File, start: u64) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(start))?;
+    let mut record = vec![0; CENTRAL_HEADER_LEN];
+    file.read_exact(&mut record)?;
+    let variable_len = [28, 30, 32]
+        .iter()
+        .map(|&at| usize::from(u16_at(&record, at)))
+        .sum::<usize>();
+    record.resize(CENTRAL_HEADER_LEN + variable_len, 0);
+    file.read_exact(&mut record[CENTRAL_HEADER_LEN..])?;
+    Ok(record)
+}
+
+/// Writes a zip file out of entries copied from others.
+pub(crate) struct Splicer<W> {
+    out: W,
+    position: u64,
+    central_directory: Vec<u8>,
+    count: u64,
+}
+
+impl<W: Write> Splicer<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            position: 0,
+            central_directory: Vec::new(),
+            count: 0,
+        }
+    }
+
+    /// Appends `entry`, read from `from`.
+    pub fn copy(&mut self, from: &mut File, entry: &RawEntry) -> Result<(), ExtractError> {
+        let mut central = entry.central.clone();
+        set_local_offset(&mut central, self.position)?;
+        from.seek(SeekFrom::Start(entry.local_start))?;
+        let len = entry.local_end - entry.local_start;
+        let copied = io::copy(&mut from.take(len), &mut self.out)?;
+        if copied != len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        self.position += len;
+        self.central_directory.extend_from_slice(&central);
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Writes the central directory and returns the writer.
+    pub fn finish(mut self, comment: &[u8]) -> Result<W, ExtractError> {
+        let start = self.position;
+        let size = self.central_directory.len() as u64;
+        self.out.write_all(&self.central_directory)?;
+        if self.count >= u64::from(u16::MAX)
+            || start >= u64::from(u32::MAX)
+            || size >= u64::from(u32::MAX)
+        {
+            let mut zip64 = Vec::with_capacity(76);
+            zip64.extend_from_slice(&ZIP64_END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+            zip64.extend_from_slice(&44u64.to_le_bytes());
+            // Made by and needed to extract: version 4.5, which added zip64.
+            zip64.extend_from_slice(&45u16.to_le_bytes());
+            zip64.extend_from_slice(&45u16.to_le_bytes());
+            zip64.extend_from_slice(&[0; 8]);
+            zip64.extend_from_slice(&self.count.to_le_bytes());
+            zip64.extend_from_slice(&self.count.to_le_bytes());
+            zip64.extend_from_slice(&size.to_le_bytes());
+            zip64.extend_from_slice(&start.to_le_bytes());
+            zip64.extend_from_slice(&ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR.to_le_bytes());
+            zip64.extend_from_slice(&[0; 4]);
+            zip64.extend_from_slice(&(start + size).to_le_bytes());
+            zip64.extend_from_slice(&1u32.to_le_bytes());
+            self.out.write_all(&zip64)?;
+        }
+        let count = self.count.min(u64::from(u16::MAX)) as u16;
+        let mut end = Vec::with_capacity(22 + comment.len());
+        end.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        end.extend_from_slice(&[0; 4]);
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&(size.min(u64::from(u32::MAX)) as u32).to_le_bytes());
+        end.extend_from_slice(&(start.min(u64::from(u32::MAX)) as u32).to_le_bytes());
+        end.extend_from_slice(&(comment.len() as u16).to_le_bytes());
+        end.extend_from_slice(comment);
+        self.out.write_all(&end)?;
+        Ok(self.out)
+    }
+}
+
+/// Points a central directory record at its local header, now at `offset`.
+/// Offsets that do not fit in 32 bits go to the zip64 extra field, after the
+/// sizes it may already hold.
+fn set_local_offset(central: &mut Vec<u8>, offset: u64) -> io::Result<()> {
+    let in_zip64 = u32_at(central, 42) == u32::MAX;
+    if !in_zip64 && offset < u64::from(u32::MAX) {
+        central[42..46].copy_from_slice(&(offset as u32).to_le_bytes());
+        return Ok(());
+    }
+    // The uncompressed and compressed sizes come first, if they overflow.
+    let at = [24, 20]
+        .iter()
+        .filter(|&&at| u32_at(central, at) == u32::MAX)
+        .count()
+        * 8;
+    let extra_start = CENTRAL_HEADER_LEN + usize::from(u16_at(central, 28));
+    let extra_len = usize::from(u16_at(central, 30));
+    let zip64 = find_extra_field(
+        &central[extra_start..extra_start + extra_len],
+        ZIP64_EXTRA_FIELD,
+    )
+    .map(|(start, len)| (extra_start + start, len));
+    match zip64 {
+        Some((data_start, len)) if in_zip64 => {
+            if len < at + 8 {
+                return Err(invalid_record());
+            }
+            let at = data_start + at;
+            central[at..at + 8].copy_from_slice(&offset.to_le_bytes());
+            return Ok(());
+        }
+        Some((data_start, len)) => {
+            if len < at {
+                return Err(invalid_record());
+            }
+            central.splice(data_start + at..data_start + at, offset.to_le_bytes());
+            set_u16(central, data_start - 2, len + 8)?;
+        }
+        None if in_zip64 => return Err(invalid_record()),
+        None => {
+            let mut field = Vec::with_capacity(12);
+            field.extend_from_slice(&ZIP64_EXTRA_FIELD.to_le_bytes());
+            field.extend_from_slice(&8u16.to_le_bytes());
+            field.extend_from_slice(&offset.to_le_bytes());
+            central.splice(extra_start..extra_start, field);
+        }
+    }
+    let added = if zip64.is_some() { 8 } else { 12 };
+    set_u16(central, 30, extra_len + added)?;
+    central[42..46].copy_from_slice(&u32::MAX.to_le_bytes());
+    Ok(())
+}
+
+/// Finds the data of an extra field, as its start and length.
+fn find_extra_field(extra: &[u8], wanted: u16) -> Option<(usize, usize)> {
+    let mut at = 0;
+    while at + 4 <= extra.len() {
+        let id = u16_at(extra, at);
+        let len = usize::from(u16_at(extra, at + 2));
+        if id == wanted {
+            return Some((at + 4, len)).filter(|_| at + 4 + len <= extra.len());
+        }
+        at += 4 + len;
+    }
+    None
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn set_u16(bytes: &mut [u8], at: usize, value: usize) -> io::Result<()> {
+    let value = u16::try_from(value).map_err(|_| invalid_record())?;
+    bytes[at..at + 2].copy_from_slice(&value.to_le_bytes());
+    Ok(())
+}
+
+fn invalid_record() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "malformed zip64 field in the central directory",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_util::scratch_dir;
+
+    /// A central directory record for `name` with the given extra field,
+    /// pointing at offset 0.
+    fn central_record(name: &str, extra: &[u8]) -> Vec<u8> {
+        let mut record = vec![0; CENTRAL_HEADER_LEN];
+        record[..4].copy_from_slice(&0x0201_4b50u32.to_le_bytes());
+        record[28..30].copy_from_slice(&(name.len() as u16).to_le_bytes());
+        record[30..32].copy_from_slice(&(extra.len() as u16).to_le_bytes());
+        record.extend_from_slice(name.as_bytes());
+        record.extend_from_slice(extra);
+        record
+    }
+
+    fn zip64_field(values: &[u64]) -> Vec<u8> {
+        let mut field = ZIP64_EXTRA_FIELD.to_le_bytes().to_vec();
+        field.extend_from_slice(&(values.len() as u16 * 8).to_le_bytes());
+        for value in values {
+            field.extend_from_slice(&value.to_le_bytes());
+        }
+        field
+    }
+
+    /// The extra field of a record.
+    fn extra(record: &[u8]) -> &[u8] {
+        let start = CENTRAL_HEADER_LEN + usize::from(u16_at(record, 28));
+        &record[start..start + usize::from(u16_at(record, 30))]
+    }
+
+    #[test]
+    fn small_offset_is_written_in_place() {
+        let mut record = central_record("a", &[]);
+        set_local_offset(&mut record, 1234).unwrap();
+        assert_eq!(u32_at(&record, 42), 1234);
+        assert_eq!(record.len(), CENTRAL_HEADER_LEN + 1);
+    }
+
+    #[test]
+    fn large_offset_adds_a_zip64_field() {
+        let offset = 5 << 32;
+        let mut record = central_record("a", &[]);
+        set_local_offset(&mut record, offset).unwrap();
+        assert_eq!(u32_at(&record, 42), u32::MAX);
+        assert_eq!(extra(&record), zip64_field(&[offset]));
+    }
+
+    #[test]
+    fn large_offset_goes_after_the_sizes() {
+        let offset = 5 << 32;
+        let mut record = central_record("a", &zip64_field(&[7 << 32, 6 << 32]));
+        record[20..28].copy_from_slice(&[0xff; 8]);
+        set_local_offset(&mut record, offset).unwrap();
+        assert_eq!(u32_at(&record, 42), u32::MAX);
+        assert_eq!(extra(&record), zip64_field(&[7 << 32, 6 << 32, offset]));
+    }
+
+    #[test]
+    fn offset_already_in_zip64_is_updated() {
+        let mut record = central_record("a", &zip64_field(&[5 << 32]));
+        record[42..46].copy_from_slice(&u32::MAX.to_le_bytes());
+        set_local_offset(&mut record, 12).unwrap();
+        assert_eq!(u32_at(&record, 42), u32::MAX);
+        assert_eq!(extra(&record), zip64_field(&[12]));
+    }
+
+    #[test]
+    fn missing_zip64_offset_is_refused() {
+        let mut record = central_record("a", &[]);
+        record[42..46].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = set_local_offset(&mut record, 12).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    /// A zip of stored entries, each followed by a data descriptor as
+    /// streaming writers leave them.
+    fn archive_with_descriptors(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut central_directory = Vec::new();
+        for (name, data) in entries {
+            let offset = bytes.len() as u32;
+            let crc32 = crc32fast::hash(data);
+            let size = data.len() as u32;
+            let mut local = vec![0; 30];
+            local[..4].copy_from_slice(&0x0403_4b50u32.to_le_bytes());
+            local[4..6].copy_from_slice(&20u16.to_le_bytes());
+            local[6..8].copy_from_slice(&8u16.to_le_bytes());
+            local[12..14].copy_from_slice(&0x21u16.to_le_bytes());
+            local[26..28].copy_from_slice(&(name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(&local);
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(data);
+            for value in [0x0807_4b50, crc32, size, size] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+
+            let mut central = central_record(name, &[]);
+            central[4..8].copy_from_slice(&[20, 0, 20, 0]);
+            central[8..10].copy_from_slice(&8u16.to_le_bytes());
+            central[14..16].copy_from_slice(&0x21u16.to_le_bytes());
+            central[16..20].copy_from_slice(&crc32.to_le_bytes());
+            central[20..24].copy_from_slice(&size.to_le_bytes());
+            central[24..28].copy_from_slice(&size.to_le_bytes());
+            central[42..46].copy_from_slice(&offset.to_le_bytes());
+            central_directory.extend_from_slice(&central);
+        }
+        let start = bytes.len() as u32;
+        bytes.extend_from_slice(&central_directory);
+        bytes.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&(central_directory.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&start.to_le_bytes());
+        bytes.extend_from_slice(&[0; 2]);
+        bytes
+    }
+
+    #[test]
+    fn entries_are_copied_with_their_data_descriptors() {
+        let dir = scratch_dir("splice-descriptors");
+        let path = dir.join("archive.zip");
+        fs::write(
+            &path,
+            archive_with_descriptors(&[("a", b"first"), ("b", b"second")]),
+        )
+        .unwrap();
+        let mut file = File::open(&path).unwrap();
+        let (entries, _) = read_entries(&mut file).unwrap();
+        // The descriptor follows the data, up to the next local header.
+        assert_eq!(entries[0].local_end, entries[1].local_start);
+        assert_eq!(entries[0].local_end, 30 + 1 + 5 + 16);
+
+        let mut splicer = Splicer::new(io::Cursor::new(Vec::new()));
+        for entry in entries.iter().rev() {
+            splicer.copy(&mut file, entry).unwrap();
+        }
+        let out = splicer.finish(b"spliced").unwrap();
+        fs::remove_dir_all(dir).unwrap();
+
+        let mut archive = zip::ZipArchive::new(out).unwrap();
+        assert_eq!(archive.comment(), b"spliced");
+        assert_eq!(archive.file_names().count(), 2);
+        for (name, data) in [("a", "first"), ("b", "second")] {
+            let mut contents = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            assert_eq!(contents, data);
+        }
+    }
+
+    #[test]
+    fn many_entries_get_zip64_end_records() {
+        let mut splicer = Splicer::new(Vec::new());
+        splicer.count = u64::from(u16::MAX);
+        let out = splicer.finish(b"").unwrap();
+        assert_eq!(out.len(), 56 + 20 + 22);
+        assert_eq!(u32_at(&out, 0), ZIP64_END_OF_CENTRAL_DIRECTORY);
+        assert_eq!(out[32..40], u64::from(u16::MAX).to_le_bytes());
+        assert_eq!(u32_at(&out, 56), ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR);
+        assert_eq!(u32_at(&out, 76), END_OF_CENTRAL_DIRECTORY);
+        assert_eq!(u16_at(&out, 86), u16::MAX);
+    }
+}
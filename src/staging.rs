@@ -0,0 +1,168 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::error::ExtractError;
+use crate::extractor::{ExtractedFile, Outcome};
+
+/// A hidden sibling of the output directory that an atomic extraction
+/// writes into, renamed into place once every entry made it.
+pub(crate) struct StagingDir {
+    path: PathBuf,
+    output_dir: PathBuf,
+}
+
+impl StagingDir {
+    /// Creates the staging directory. The output directory must not exist
+    /// yet, or be empty, since a rename cannot merge two trees.
+    pub fn create(output_dir: &Path) -> Result<Self, ExtractError> {
+        let name = output_dir.file_name().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "cannot extract atomically into {}; name the output directory",
+                    output_dir.display()
+                ),
+            )
+        })?;
+        match fs::metadata(output_dir) {
+            Ok(metadata) if !metadata.is_dir() => {
+                return Err(ExtractError::output_dir(
+                    output_dir,
+                    io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        "exists and is not a directory",
+                    ),
+                ))
+            }
+            Ok(_) if fs::read_dir(output_dir).is_ok_and(|mut dir| dir.next().is_some()) => {
+                return Err(ExtractError::AlreadyExists(output_dir.to_path_buf()))
+            }
+            _ => {}
+        }
+        let staging_name = format!(".{}.partial-{}", name.to_string_lossy(), process::id());
+        let path = output_dir.with_file_name(staging_name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|err| ExtractError::output_dir(parent, err))?;
+        }
+        fs::create_dir(&path).map_err(|err| ExtractError::output_dir(output_dir, err))?;
+        Ok(Self {
+            path,
+            output_dir: output_dir.to_path_buf(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Moves the staged tree to the output directory and points the extracted
+    /// files at where they ended up.
+    pub fn commit(self, extracted_files: &mut [ExtractedFile]) -> Result<(), ExtractError> {
+        // Only an empty directory can be in the way, as checked on creation.
+        match fs::remove_dir(&self.output_dir) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                let err = ExtractError::output_dir(&self.output_dir, err);
+                self.discard();
+                return Err(err);
+            }
+            _ => {}
+        }
+        if let Err(err) = fs::rename(&self.path, &self.output_dir) {
+            let err = ExtractError::output_dir(&self.output_dir, err);
+            self.discard();
+            return Err(err);
+        }
+        for extracted_file in extracted_files {
+            extracted_file.path = self.rebase(&extracted_file.path);
+            if let Outcome::Renamed(path) = &extracted_file.outcome {
+                extracted_file.outcome = Outcome::Renamed(self.rebase(path));
+            }
+            if let Some(target) = &extracted_file.link_target {
+                extracted_file.link_target = Some(self.rebase(target));
+            }
+        }
+        Ok(())
+    }
+
+    /// Points the paths in `err` at where the entries were headed instead of
+    /// into the staged tree, which the user never asked for.
+    pub fn rebase_error(&self, err: ExtractError) -> ExtractError {
+        match err {
+            ExtractError::InEntry { name, path, source } => ExtractError::InEntry {
+                name,
+                path: path.map(|path| self.rebase(&path)),
+                source: Box::new(self.rebase_error(*source)),
+            },
+            ExtractError::AlreadyExists(path) => ExtractError::AlreadyExists(self.rebase(&path)),
+            err => err,
+        }
+    }
+
+    /// Removes the staged tree, leaving the output directory untouched.
+    pub fn discard(self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+
+    fn rebase(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.path) {
+            Ok(relative) => self.output_dir.join(relative),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::scratch_dir;
+
+    #[test]
+    fn refuses_a_file_as_output_dir() {
+        let dir = scratch_dir("staging-file");
+        let output_dir = dir.join("out");
+        fs::write(&output_dir, b"").unwrap();
+        match StagingDir::create(&output_dir) {
+            Err(ExtractError::OutputDir { path, .. }) => assert_eq!(path, output_dir),
+            result => panic!("expected the file to be refused, got {:?}", result.err()),
+        }
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn errors_name_the_output_dir() {
+        let dir = scratch_dir("staging-error");
+        let output_dir = dir.join("out");
+        let staging_dir = StagingDir::create(&output_dir).unwrap();
+        let staged = staging_dir.path().join("a");
+        let err = ExtractError::AlreadyExists(staged.clone()).in_entry("a", Some(&staged));
+        let err = staging_dir.rebase_error(err);
+        let expected = output_dir.join("a");
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "a (to {}): {} already exists",
+                expected.display(),
+                expected.display()
+            )
+        );
+        staging_dir.discard();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_commit_removes_staged_tree() {
+        let dir = scratch_dir("staging-commit");
+        let output_dir = dir.join("out");
+        let staging_dir = StagingDir::create(&output_dir).unwrap();
+        let staged = staging_dir.path().to_path_buf();
+        // Something filled the output directory in the meantime.
+        fs::create_dir(&output_dir).unwrap();
+        fs::write(output_dir.join("file"), b"").unwrap();
+        assert!(staging_dir.commit(&mut []).is_err());
+        assert!(!staged.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
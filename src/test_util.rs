@@ -0,0 +1,151 @@
+//! Helpers shared by the unit tests.
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process;
+
+use crate::archive::Entry;
+use crate::extractor::FileKind;
+
+/// An empty directory of its own under the system's temporary directory,
+/// named after the test using it.
+pub(crate) fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rust_decompress-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A regular file entry as the archive would describe it.
+pub(crate) fn file_entry(name: &str, size: u64, compressed_size: Option<u64>) -> Entry {
+    Entry {
+        index: 0,
+        name: name.to_string(),
+        enclosed_name: Some(PathBuf::from(name)),
+        kind: FileKind::File { size },
+        link_target: None,
+        size,
+        compressed_size,
+        method: "Deflated".to_string(),
+        modified: None,
+        unix_mode: None,
+        crc32: None,
+        encryption: None,
+    }
+}
+
+/// A zip64 archive of deflated entries named `a`, `b`, … that each hold five
+/// bytes but declare the given sizes, as a hostile archive would.
+pub(crate) fn zip64_archive(sizes: &[u64]) -> Vec<u8> {
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), Default::default());
+    encoder.write_all(b"hello").unwrap();
+    let data = encoder.finish().unwrap();
+
+    let mut bytes = Vec::new();
+    let mut central_directory = Vec::new();
+    for (index, &size) in sizes.iter().enumerate() {
+        let name = [b'a' + index as u8];
+        let offset = bytes.len() as u32;
+        let mut extra = Vec::new();
+        extra.extend_from_slice(&1u16.to_le_bytes());
+        extra.extend_from_slice(&16u16.to_le_bytes());
+        extra.extend_from_slice(&size.to_le_bytes());
+        extra.extend_from_slice(&(data.len() as u64).to_le_bytes());
+
+        // Version 4.5, no flags, deflated, 1980-01-01, no CRC-32, sizes in
+        // the zip64 field.
+        let common = [
+            &45u16.to_le_bytes()[..],
+            &0u16.to_le_bytes(),
+            &8u16.to_le_bytes(),
+            &0u16.to_le_bytes(),
+            &0x21u16.to_le_bytes(),
+            &0u32.to_le_bytes(),
+            &u32::MAX.to_le_bytes(),
+            &u32::MAX.to_le_bytes(),
+            &1u16.to_le_bytes(),
+            &(extra.len() as u16).to_le_bytes(),
+        ]
+        .concat();
+        bytes.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        bytes.extend_from_slice(&common);
+        bytes.extend_from_slice(&name);
+        bytes.extend_from_slice(&extra);
+        bytes.extend_from_slice(&data);
+
+        central_directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        central_directory.extend_from_slice(&45u16.to_le_bytes());
+        central_directory.extend_from_slice(&common);
+        // No comment, disk 0, no attributes.
+        central_directory.extend_from_slice(&[0; 10]);
+        central_directory.extend_from_slice(&offset.to_le_bytes());
+        central_directory.extend_from_slice(&name);
+        central_directory.extend_from_slice(&extra);
+    }
+    let start = bytes.len() as u32;
+    bytes.extend_from_slice(&central_directory);
+    bytes.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(&(sizes.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(&(sizes.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(&(central_directory.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&start.to_le_bytes());
+    bytes.extend_from_slice(&[0; 2]);
+    bytes
+}
+
+/// A zip of one deflated entry whose CRC-32 and sizes only follow its data,
+/// in a data descriptor, as a writer streaming the archive leaves them.
+pub(crate) fn zip_with_descriptor(name: &str, data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), Default::default());
+    encoder.write_all(data).unwrap();
+    let compressed = encoder.finish().unwrap();
+    let crc32 = crc32fast::hash(data);
+
+    // Version 2.0, sizes in a descriptor, deflated, 1980-01-01.
+    let common = |crc32: u32, compressed_size: u32, size: u32| {
+        [
+            &20u16.to_le_bytes()[..],
+            &8u16.to_le_bytes(),
+            &8u16.to_le_bytes(),
+            &0u16.to_le_bytes(),
+            &0x21u16.to_le_bytes(),
+            &crc32.to_le_bytes(),
+            &compressed_size.to_le_bytes(),
+            &size.to_le_bytes(),
+            &(name.len() as u16).to_le_bytes(),
+            &0u16.to_le_bytes(),
+        ]
+        .concat()
+    };
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+    bytes.extend_from_slice(&common(0, 0, 0));
+    bytes.extend_from_slice(name.as_bytes());
+    bytes.extend_from_slice(&compressed);
+    for value in [
+        0x0807_4b50,
+        crc32,
+        compressed.len() as u32,
+        data.len() as u32,
+    ] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let start = bytes.len() as u32;
+    let mut central = Vec::new();
+    central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+    central.extend_from_slice(&20u16.to_le_bytes());
+    central.extend_from_slice(&common(crc32, compressed.len() as u32, data.len() as u32));
+    // No comment, disk 0, no attributes, local header at the start.
+    central.extend_from_slice(&[0; 14]);
+    central.extend_from_slice(name.as_bytes());
+    bytes.extend_from_slice(&central);
+    bytes.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    bytes.extend_from_slice(&[0, 0, 0, 0, 1, 0, 1, 0]);
+    bytes.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&start.to_le_bytes());
+    bytes.extend_from_slice(&[0; 2]);
+    bytes
+}